@@ -0,0 +1,286 @@
+use std::{
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::{
+    message::OutputInfo,
+    swww_ffi::{self, TransitionOptions},
+};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, BackendError>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BackendError {
+    /// Nothing can be shown right now, e.g. because swww-daemon is not running yet. Worth
+    /// trying again later.
+    #[error("{0}")]
+    Unavailable(String),
+    /// This wallpaper could not be shown.
+    #[error("{0}")]
+    Failed(String),
+}
+
+/// Something which can put a wallpaper on screen.
+pub trait WallpaperBackend: Send + Sync + std::fmt::Debug {
+    /// Show `path` on `outputs`, or on every output if empty.
+    fn set_wallpaper<'a>(
+        &'a self,
+        path: &'a str,
+        outputs: &'a [String],
+        transition: &'a TransitionOptions,
+    ) -> BackendFuture<'a, ()>;
+
+    /// Outputs wallpapers can be shown on, in order. Backends which can not tell report none,
+    /// which shows the same wallpaper everywhere.
+    fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>>;
+
+    /// Fails with [`BackendError::Unavailable`] until wallpapers can be shown.
+    fn ready(&self) -> BackendFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Which [`WallpaperBackend`] the daemon uses.
+///
+/// ```toml
+/// [backend]
+/// type = "command"
+/// command = ["swaybg", "-m", "fill", "-i", "{path}"]
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendConfig {
+    #[default]
+    Swww,
+    /// Runs `command` for every switch. `{path}`, `{outputs}` and `{transition}` in its
+    /// arguments are replaced by the wallpaper, the comma separated outputs and the transition
+    /// type.
+    Command { command: Vec<String> },
+    /// Shows nothing and records every switch instead, optionally appending them to `log` as
+    /// JSON lines.
+    Recording {
+        #[serde(default)]
+        outputs: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        log: Option<PathBuf>,
+    },
+}
+
+impl BackendConfig {
+    pub fn build(&self) -> anyhow::Result<Arc<dyn WallpaperBackend>> {
+        Ok(match self {
+            BackendConfig::Swww => Arc::new(SwwwBackend),
+            BackendConfig::Command { command } => Arc::new(CommandBackend::new(command.clone())?),
+            BackendConfig::Recording { outputs, log } => {
+                Arc::new(RecordingBackend::new(outputs.clone(), log.clone()))
+            }
+        })
+    }
+}
+
+/// Shows wallpapers with `swww img`.
+#[derive(Debug)]
+pub struct SwwwBackend;
+
+impl WallpaperBackend for SwwwBackend {
+    fn set_wallpaper<'a>(
+        &'a self,
+        path: &'a str,
+        outputs: &'a [String],
+        transition: &'a TransitionOptions,
+    ) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            let Err(err) = swww_ffi::set_background(path, outputs, transition).await else {
+                return Ok(());
+            };
+
+            // swww img fails the same way for a broken image and a missing daemon, so ask the
+            // daemon whether it is there.
+            match swww_ffi::query_outputs().await {
+                Ok(_) => Err(BackendError::Failed(err)),
+                Err(_) => Err(BackendError::Unavailable(err)),
+            }
+        })
+    }
+
+    fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>> {
+        Box::pin(async {
+            swww_ffi::query_outputs()
+                .await
+                .map_err(BackendError::Unavailable)
+        })
+    }
+
+    fn ready(&self) -> BackendFuture<'_, ()> {
+        Box::pin(async {
+            swww_ffi::query_outputs()
+                .await
+                .map(|_| ())
+                .map_err(BackendError::Unavailable)
+        })
+    }
+}
+
+/// Shows wallpapers by running a command template, see [`BackendConfig::Command`].
+#[derive(Debug)]
+pub struct CommandBackend {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandBackend {
+    pub fn new(mut command: Vec<String>) -> anyhow::Result<Self> {
+        if command.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The command backend needs a command to run"
+            ));
+        }
+
+        Ok(Self {
+            program: command.remove(0),
+            args: command,
+        })
+    }
+}
+
+impl WallpaperBackend for CommandBackend {
+    fn set_wallpaper<'a>(
+        &'a self,
+        path: &'a str,
+        outputs: &'a [String],
+        transition: &'a TransitionOptions,
+    ) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            let outputs = outputs.join(",");
+            let args = self.args.iter().map(|arg| {
+                arg.replace("{path}", path)
+                    .replace("{outputs}", &outputs)
+                    .replace("{transition}", &transition.transition_type)
+            });
+
+            let output = Command::new(&self.program)
+                .args(args)
+                .output()
+                .await
+                .map_err(|err| {
+                    BackendError::Unavailable(format!("Could not run {}: {err}", self.program))
+                })?;
+
+            match output.status.success() {
+                true => Ok(()),
+                false => Err(BackendError::Failed(swww_ffi::describe_failure(
+                    &self.program,
+                    &output,
+                ))),
+            }
+        })
+    }
+
+    fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>> {
+        Box::pin(async { Ok(vec![]) })
+    }
+}
+
+/// A switch seen by a [`RecordingBackend`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedWallpaper {
+    pub path: String,
+    pub outputs: Vec<String>,
+    pub transition: TransitionOptions,
+    /// Milliseconds since the Unix epoch.
+    pub at: u64,
+}
+
+/// Remembers every switch instead of showing anything, for checking the scheduler without a
+/// Wayland session.
+#[derive(Clone, Default, Debug)]
+pub struct RecordingBackend {
+    outputs: Vec<String>,
+    log: Option<PathBuf>,
+    applied: Arc<Mutex<Vec<AppliedWallpaper>>>,
+}
+
+impl RecordingBackend {
+    /// Pretends to draw on `outputs`. Every switch is appended to `log` if given.
+    pub fn new(outputs: Vec<String>, log: Option<PathBuf>) -> Self {
+        Self {
+            outputs,
+            log,
+            applied: Default::default(),
+        }
+    }
+
+    /// Every switch so far, oldest first.
+    pub fn applied(&self) -> Vec<AppliedWallpaper> {
+        self.applied.lock().unwrap().clone()
+    }
+
+    fn record(&self, applied: AppliedWallpaper) -> Result<(), BackendError> {
+        if let Some(log) = &self.log {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log)
+                .map_err(|err| {
+                    BackendError::Unavailable(format!("Could not open {}: {err}", log.display()))
+                })?;
+            let line = serde_json::to_string(&applied)
+                .map_err(|err| BackendError::Failed(err.to_string()))?;
+            writeln!(file, "{line}").map_err(|err| {
+                BackendError::Unavailable(format!("Could not write to {}: {err}", log.display()))
+            })?;
+        }
+
+        self.applied.lock().unwrap().push(applied);
+        Ok(())
+    }
+}
+
+impl WallpaperBackend for RecordingBackend {
+    fn set_wallpaper<'a>(
+        &'a self,
+        path: &'a str,
+        outputs: &'a [String],
+        transition: &'a TransitionOptions,
+    ) -> BackendFuture<'a, ()> {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+
+        let result = self.record(AppliedWallpaper {
+            path: path.to_string(),
+            outputs: outputs.to_vec(),
+            transition: transition.clone(),
+            at,
+        });
+        Box::pin(async move { result })
+    }
+
+    fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>> {
+        let applied = self.applied.lock().unwrap();
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|name| OutputInfo {
+                name: name.clone(),
+                wallpaper: applied
+                    .iter()
+                    .rev()
+                    .find(|applied| applied.outputs.is_empty() || applied.outputs.contains(name))
+                    .map(|applied| applied.path.clone()),
+            })
+            .collect();
+        Box::pin(async move { Ok(outputs) })
+    }
+}
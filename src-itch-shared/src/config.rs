@@ -0,0 +1,397 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BackendConfig,
+    duration,
+    message::{OutputMode, RotationMode},
+    schedule::ScheduleRule,
+    sun::Location,
+    swww_ffi::TransitionOptions,
+    unix_socket::UnixSocketPath,
+};
+
+pub const DEFAULT_SOCKET_NAME: &str = "swwwitch.sock";
+
+/// Settings shared by swww-itchd and its clients, read from
+/// `$XDG_CONFIG_HOME/swww-itch/config.toml`.
+///
+/// ```toml
+/// directories = [
+///     "~/backgrounds",
+///     { path = "/mnt/art", recursive = true, max_depth = 2, exclude = ["drafts/**"] },
+/// ]
+/// interval = "1h"
+/// align = true
+/// mode = "shuffle"
+/// output_mode = "independent"
+/// startup_wallpaper = "~/backgrounds/forest.png"
+/// socket_path = "/run/user/1000/swwwitch.sock"
+///
+/// [backend]
+/// type = "swww"
+///
+/// [transition]
+/// type = "wipe"
+/// angle = 30
+/// duration = 2.5
+/// fps = 144
+///
+/// [[schedule]]
+/// from = "18:00"
+/// to = "07:00"
+/// playlist = "night"
+///
+/// [location]
+/// latitude = 52.52
+/// longitude = 13.40
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", default)]
+pub struct Config {
+    /// Directories to queue wallpapers from. Sources set over IPC take precedence.
+    pub directories: Vec<WallpaperSource>,
+    /// Default rotation interval. An interval set over IPC takes precedence.
+    #[serde(with = "duration::human")]
+    pub interval: Duration,
+    /// Switch on multiples of the interval counted from midnight, e.g. on the hour, instead of
+    /// counting from the last switch.
+    pub align: bool,
+    /// Default rotation mode. A mode set over IPC takes precedence.
+    pub mode: RotationMode,
+    /// Seed for the shuffle and random modes, which are seeded from the OS if unset. Useful to
+    /// get the same order on every start.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Whether every output shows the same wallpaper, its own position in the queue, or the
+    /// next one along from its neighbour.
+    pub output_mode: OutputMode,
+    /// Wallpaper to apply once the daemon has started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_wallpaper: Option<String>,
+    /// Defaults to `$XDG_RUNTIME_DIR/swwwitch.sock`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket_path: Option<PathBuf>,
+    /// What shows the wallpapers, swww unless set.
+    pub backend: BackendConfig,
+    /// Default transition. Backgrounds can be given their own over IPC.
+    pub transition: TransitionOptions,
+    /// Rules picking the playlist and switch times by the time of day.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduleRule>,
+    /// Where to follow the sun for schedule rules with `when`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            directories: vec![WallpaperSource::new("~/backgrounds")],
+            interval: Duration::from_secs(60 * 60),
+            align: false,
+            mode: RotationMode::default(),
+            seed: None,
+            output_mode: OutputMode::default(),
+            startup_wallpaper: None,
+            socket_path: None,
+            backend: BackendConfig::default(),
+            transition: TransitionOptions::default(),
+            schedule: vec![],
+            location: None,
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/swww-itch/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> anyhow::Result<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::home_dir()
+                .ok_or(anyhow!("Could not get home directory"))?
+                .join(".config"),
+        };
+
+        Ok(config_home.join("swww-itch").join("config.toml"))
+    }
+
+    /// A missing file yields the default config, a malformed one is an error.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+
+        match fs::read_to_string(path) {
+            Ok(s) => toml::from_str(&s)
+                .map_err(|err| anyhow!("Invalid config file {}: {err}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(anyhow!("Could not read {}: {err}", path.display())),
+        }
+    }
+
+    pub fn load_default() -> anyhow::Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// Written to a temporary file first and renamed over `path`, so that the daemon never
+    /// reads a half written config.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp_path = path.with_extension("toml.tmp");
+        let mut tmp = fs::File::create(&tmp_path)?;
+        tmp.write_all(toml::to_string_pretty(self)?.as_bytes())?;
+        tmp.sync_all()?;
+        drop(tmp);
+
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn save_default(&self) -> anyhow::Result<()> {
+        self.save(Self::default_path()?)
+    }
+
+    /// Root of every source in [`Config::directories`] with `~` expanded.
+    pub fn directories(&self) -> Vec<PathBuf> {
+        self.directories.iter().map(WallpaperSource::root).collect()
+    }
+
+    pub fn startup_wallpaper(&self) -> Option<PathBuf> {
+        self.startup_wallpaper.as_deref().map(expand_home)
+    }
+
+    pub fn socket_path(&self) -> anyhow::Result<PathBuf> {
+        match self.socket_path.as_ref() {
+            Some(path) => Ok(expand_home(&path.to_string_lossy())),
+            None => Ok(UnixSocketPath::RuntimeDir(DEFAULT_SOCKET_NAME).to_pathbuf()?),
+        }
+    }
+}
+
+/// A directory to queue wallpapers from.
+///
+/// Written as a plain path string when only the top level of the directory should be scanned.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(from = "SourceRepr", into = "SourceRepr")]
+pub struct WallpaperSource {
+    /// A leading `~` is expanded to the home directory.
+    pub path: String,
+    /// Descend into subdirectories.
+    pub recursive: bool,
+    /// How many levels of subdirectories to descend into when recursive. Unlimited if unset.
+    pub max_depth: Option<usize>,
+    /// Glob patterns matched against the path relative to the source root. A wallpaper must
+    /// match at least one of them, unless there are none.
+    pub include: Vec<String>,
+    /// Glob patterns matched against the path relative to the source root.
+    pub exclude: Vec<String>,
+}
+
+impl WallpaperSource {
+    pub fn new<S: Into<String>>(path: S) -> Self {
+        Self {
+            path: path.into(),
+            recursive: false,
+            max_depth: None,
+            include: vec![],
+            exclude: vec![],
+        }
+    }
+
+    pub fn root(&self) -> PathBuf {
+        expand_home(&self.path)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SourceRepr {
+    Path(String),
+    Full {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_depth: Option<usize>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        include: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        exclude: Vec<String>,
+    },
+}
+
+impl From<SourceRepr> for WallpaperSource {
+    fn from(repr: SourceRepr) -> Self {
+        match repr {
+            SourceRepr::Path(path) => Self::new(path),
+            SourceRepr::Full {
+                path,
+                recursive,
+                max_depth,
+                include,
+                exclude,
+            } => Self {
+                path,
+                recursive,
+                max_depth,
+                include,
+                exclude,
+            },
+        }
+    }
+}
+
+impl From<WallpaperSource> for SourceRepr {
+    fn from(source: WallpaperSource) -> Self {
+        if source == WallpaperSource::new(source.path.clone()) {
+            return SourceRepr::Path(source.path);
+        }
+
+        SourceRepr::Full {
+            path: source.path,
+            recursive: source.recursive,
+            max_depth: source.max_depth,
+            include: source.include,
+            exclude: source.exclude,
+        }
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::home_dir();
+
+    match (path.strip_prefix("~"), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where a test keeps its config file, which does not exist yet.
+    fn config_path(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("swww-itch-{}-config-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("config.toml")
+    }
+
+    #[test]
+    fn loads_the_default_config_without_a_file() {
+        let path = config_path("missing");
+
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn fills_in_defaults_for_settings_left_out() {
+        let path = config_path("partial");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "interval = \"15m\"\nmode = \"shuffle\"\n").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.interval, Duration::from_secs(15 * 60));
+        assert_eq!(config.mode, RotationMode::Shuffle);
+        assert_eq!(config.directories, Config::default().directories);
+        assert_eq!(config.transition, TransitionOptions::default());
+    }
+
+    #[test]
+    fn reads_directories_as_paths_or_tables() {
+        let config: Config = toml::from_str(
+            r#"
+            directories = [
+                "~/backgrounds",
+                { path = "/mnt/art", recursive = true, max_depth = 2, exclude = ["drafts/**"] },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.directories,
+            [
+                WallpaperSource::new("~/backgrounds"),
+                WallpaperSource {
+                    recursive: true,
+                    max_depth: Some(2),
+                    exclude: vec!["drafts/**".to_string()],
+                    ..WallpaperSource::new("/mnt/art")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_malformed_file() {
+        let path = config_path("malformed");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "interval = \"5x\"\n").unwrap();
+
+        let err = Config::load(&path).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("Invalid config file {}", path.display())));
+    }
+
+    #[test]
+    fn restores_what_was_saved() {
+        let path = config_path("save");
+        let config: Config = toml::from_str(
+            r#"
+            directories = [{ path = "/mnt/art", recursive = true, include = ["*.png"] }]
+            interval = "1h30m"
+            align = true
+            output_mode = "spanned"
+            seed = 7
+
+            [transition]
+            type = "wipe"
+            angle = 30
+            fill_color = "000000"
+
+            [[schedule]]
+            from = "18:00"
+            to = "07:00"
+            playlist = "night"
+
+            [location]
+            latitude = 52.52
+            longitude = 13.40
+            "#,
+        )
+        .unwrap();
+
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+
+        Config::default().save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn leaves_no_temporary_file_behind() {
+        let path = config_path("tmp");
+
+        Config::default().save(&path).unwrap();
+
+        let files: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, ["config.toml"]);
+    }
+}
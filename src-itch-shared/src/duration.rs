@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+/// Parse human friendly durations like `90`, `15m`, `2h` or `1h30m`.
+///
+/// A bare number is taken as seconds. Supported units are `s`, `m`, `h` and `d`.
+pub fn parse_duration(s: &str) -> Result<Duration, &'static str> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("Duration must not be empty");
+    }
+
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err("Duration units must be one of 's', 'm', 'h' or 'd'"),
+        };
+
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| "Every duration unit must be preceded by a number")?;
+        digits.clear();
+
+        total = value
+            .checked_mul(unit)
+            .and_then(|v| total.checked_add(v))
+            .ok_or("Duration is too long")?;
+    }
+
+    if !digits.is_empty() {
+        return Err("Every number in a duration must be followed by a unit");
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+/// Inverse of [`parse_duration`], dropping units which are zero. Sub-second precision is lost.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    if secs == 0 {
+        return "0s".to_string();
+    }
+
+    let mut out = String::new();
+    for (unit, unit_secs) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+        if secs >= unit_secs {
+            out.push_str(&format!("{}{unit}", secs / unit_secs));
+            secs %= unit_secs;
+        }
+    }
+    out
+}
+
+/// (De)serialize a [`Duration`] as a human friendly string, for use with `#[serde(with = "...")]`.
+///
+/// Integers are accepted as seconds when deserializing.
+pub mod human {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Secs(u64),
+        Human(String),
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Secs(secs) => Ok(Duration::from_secs(secs)),
+            Repr::Human(s) => parse_duration(&s).map_err(D::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Interval {
+        #[serde(with = "human")]
+        interval: Duration,
+    }
+
+    fn secs(s: &str) -> u64 {
+        parse_duration(s).unwrap().as_secs()
+    }
+
+    #[test]
+    fn parses_numbers_as_seconds() {
+        assert_eq!(secs("90"), 90);
+        assert_eq!(secs("0"), 0);
+        assert_eq!(secs(" 15 "), 15);
+    }
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(secs("45s"), 45);
+        assert_eq!(secs("15m"), 15 * 60);
+        assert_eq!(secs("2h"), 2 * 60 * 60);
+        assert_eq!(secs("1d"), 24 * 60 * 60);
+        assert_eq!(secs("1h30m"), 90 * 60);
+        assert_eq!(secs("1h 30m"), 90 * 60);
+        assert_eq!(secs("1d2h3m4s"), 93784);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        assert_eq!(parse_duration(""), Err("Duration must not be empty"));
+        assert_eq!(parse_duration("  "), Err("Duration must not be empty"));
+        assert_eq!(
+            parse_duration("5x"),
+            Err("Duration units must be one of 's', 'm', 'h' or 'd'")
+        );
+        assert_eq!(
+            parse_duration("-5m"),
+            Err("Duration units must be one of 's', 'm', 'h' or 'd'")
+        );
+        assert_eq!(
+            parse_duration("m"),
+            Err("Every duration unit must be preceded by a number")
+        );
+        assert_eq!(
+            parse_duration("1h30"),
+            Err("Every number in a duration must be followed by a unit")
+        );
+    }
+
+    #[test]
+    fn rejects_durations_which_overflow() {
+        assert_eq!(
+            parse_duration("100000000000000000h"),
+            Err("Duration is too long")
+        );
+        assert_eq!(
+            parse_duration("18446744073709551615s1s"),
+            Err("Duration is too long")
+        );
+    }
+
+    #[test]
+    fn formats_without_zero_units() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_secs(90)), "1m30s");
+        assert_eq!(format_duration(Duration::from_secs(2 * 60 * 60)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(93784)), "1d2h3m4s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1s");
+    }
+
+    #[test]
+    fn formats_durations_which_parse_back() {
+        for secs in [0, 1, 59, 60, 90, 3600, 5400, 86400, 93784, u64::MAX] {
+            let duration = Duration::from_secs(secs);
+            assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+        }
+    }
+
+    #[test]
+    fn serializes_human_friendly_strings() {
+        let interval = Interval {
+            interval: Duration::from_secs(90 * 60),
+        };
+        let toml = toml::to_string(&interval).unwrap();
+
+        assert_eq!(toml.trim(), r#"interval = "1h30m""#);
+        assert_eq!(toml::from_str::<Interval>(&toml).unwrap(), interval);
+    }
+
+    #[test]
+    fn deserializes_integers_as_seconds() {
+        let interval: Interval = toml::from_str("interval = 90").unwrap();
+        assert_eq!(interval.interval, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn fails_to_deserialize_a_malformed_duration() {
+        let err = toml::from_str::<Interval>(r#"interval = "5x""#).unwrap_err();
+        assert!(err.to_string().contains("Duration units must be one of"));
+    }
+}
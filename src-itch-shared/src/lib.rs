@@ -1,3 +1,8 @@
+pub mod backend;
+pub mod config;
+pub mod duration;
 pub mod message;
+pub mod schedule;
+pub mod sun;
 pub mod swww_ffi;
 pub mod unix_socket;
@@ -1,21 +1,295 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{config::WallpaperSource, sun::SolarPhase, swww_ffi::TransitionOptions};
+
+pub use error::{ItchError, ItchResult};
+pub use history::SwitchReason;
+pub use output::OutputMode;
 pub use rearrange::Position;
+pub use rotation::RotationMode;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Request {
-    SwitchToBackground(String),
+    /// Background and the output to show it on. Without an output the background is shown the
+    /// way the [`OutputMode`] shows the queue.
+    SwitchToBackground((String, Option<String>)),
     RearrangeBackground((String, Position, String)),
+    /// Apply the upcoming wallpaper now.
+    Next,
+    /// Go back to the wallpaper shown before the current one.
+    Previous,
+    /// Show the wallpaper from before the current one in the history again, whatever the
+    /// rotation mode. Going back again goes further back.
+    Back,
+    /// Freeze the rotation timer.
+    Pause,
+    /// Continue a paused rotation timer.
+    Resume,
+    /// Rotation interval in seconds.
+    SetInterval(u64),
+    GetInterval,
+    /// How the upcoming wallpaper is picked after each switch.
+    SetMode(RotationMode),
+    GetMode,
     GetQueue,
+    /// Outputs swww is drawing on and what they currently show.
+    GetOutputs,
+    /// How wallpapers are spread over the outputs from the next switch on.
+    SetOutputMode(OutputMode),
+    ListPlaylists,
+    /// Name of the new playlist and the backgrounds it holds, in order. Without backgrounds it
+    /// holds every background and picks up new ones as they appear.
+    CreatePlaylist((String, Option<Vec<String>>)),
+    DeletePlaylist(String),
+    /// Current name and new name.
+    RenamePlaylist((String, String)),
+    /// Play a playlist with its own order, interval and rotation mode.
+    ActivatePlaylist(String),
+    /// Name of a playlist and the phase of the sun it is played in. Without a phase it is only
+    /// played when asked for.
+    SetPlaylistPhase((String, Option<SolarPhase>)),
+    /// Transition used for a background, or the default transition without one.
+    GetTransition(Option<String>),
+    /// Transition to use for a background in place of the default one. Without a transition
+    /// the background goes back to the default.
+    SetTransition((String, Option<TransitionOptions>)),
+    /// Show a background with the given transition right away, leaving the queue alone.
+    PreviewTransition((String, TransitionOptions)),
+    /// Display duration, weight, pin and phase of a background.
+    GetWallpaperMeta(String),
+    /// Replace the display duration, weight, pin and phase of a background. The default clears
+    /// them.
+    SetWallpaperMeta((String, WallpaperMeta)),
+    /// Format and dimensions of a queued background.
+    GetImageInfo(String),
+    /// Files in the wallpaper sources which are not usable images.
+    GetRejected,
+    /// Wallpapers the backend failed to show since they last worked.
+    GetFailures,
+    GetSources,
+    /// Replace the directories wallpapers are queued from and rescan them.
+    SetSources(Vec<WallpaperSource>),
+    /// What the daemon is showing and when it switches next.
+    GetStatus,
+    /// Wallpapers shown lately, newest first.
+    GetHistory,
+    /// Turn the connection into an event stream. Events are pushed as [`Response::Event`].
+    Subscribe,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum Response {
-    SwitchToBackground(bool),
-    RearrangeBackground((bool, usize, usize)),
-    GetQueue(Vec<String>),
+    SwitchToBackground(ItchResult<()>),
+    /// Index the background was moved from and the index it was moved to.
+    RearrangeBackground(ItchResult<(usize, usize)>),
+    Next(ItchResult<()>),
+    Previous(ItchResult<()>),
+    Back(ItchResult<()>),
+    Pause(ItchResult<()>),
+    Resume(ItchResult<()>),
+    SetInterval(ItchResult<()>),
+    /// Rotation interval in seconds.
+    GetInterval(ItchResult<u64>),
+    SetMode(ItchResult<()>),
+    GetMode(ItchResult<RotationMode>),
+    GetQueue(ItchResult<Vec<String>>),
+    GetOutputs(ItchResult<Vec<OutputInfo>>),
+    SetOutputMode(ItchResult<()>),
+    ListPlaylists(ItchResult<Vec<PlaylistInfo>>),
+    CreatePlaylist(ItchResult<()>),
+    DeletePlaylist(ItchResult<()>),
+    RenamePlaylist(ItchResult<()>),
+    ActivatePlaylist(ItchResult<()>),
+    SetPlaylistPhase(ItchResult<()>),
+    GetTransition(ItchResult<TransitionOptions>),
+    SetTransition(ItchResult<()>),
+    PreviewTransition(ItchResult<()>),
+    GetWallpaperMeta(ItchResult<WallpaperMeta>),
+    SetWallpaperMeta(ItchResult<()>),
+    GetImageInfo(ItchResult<ImageInfo>),
+    GetRejected(ItchResult<Vec<RejectedFile>>),
+    GetFailures(ItchResult<Vec<FailedWallpaper>>),
+    GetSources(ItchResult<Vec<WallpaperSource>>),
+    SetSources(ItchResult<()>),
+    GetStatus(ItchResult<Status>),
+    GetHistory(ItchResult<Vec<HistoryEntry>>),
+    Subscribe(ItchResult<()>),
+    Event(Event),
+}
+
+/// State changes pushed to subscribed clients.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum Event {
+    /// Path and queue index of the wallpaper which was just applied, and the output it was
+    /// applied to. Without an output it was applied to every output.
+    WallpaperChanged((String, usize, Option<String>)),
+    /// Index a background was moved from and the index it was moved to.
+    QueueReordered((usize, usize)),
+    /// The set of queued backgrounds changed. Carries the new queue.
+    QueueChanged(Vec<String>),
+    /// New rotation interval in seconds.
+    IntervalChanged(u64),
+    /// Whether the rotation timer is paused.
+    PausedChanged(bool),
+    ModeChanged(RotationMode),
+    /// A playlist was created, deleted, renamed or activated. Carries every playlist.
+    PlaylistsChanged(Vec<PlaylistInfo>),
+    /// A file appeared in a wallpaper source but is not a usable image.
+    FileRejected(RejectedFile),
+    /// The backend failed to show a wallpaper.
+    WallpaperFailed(FailedWallpaper),
+    /// A different schedule rule applies now. Carries its name, or nothing if none applies.
+    RuleChanged(Option<String>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistInfo {
+    pub name: String,
+    pub active: bool,
+    /// Whether backgrounds which appear in the wallpaper sources are added to the playlist.
+    pub follows_sources: bool,
+    /// Number of backgrounds in the playlist.
+    pub length: usize,
+    /// Rotation interval in seconds.
+    pub interval: u64,
+    pub mode: RotationMode,
+    /// Phase of the sun the playlist is activated in.
+    pub phase: Option<SolarPhase>,
+}
+
+/// An output swww is drawing on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputInfo {
+    pub name: String,
+    /// The image swww reports the output is showing, if it is showing one.
+    pub wallpaper: Option<String>,
+}
+
+/// What the header of a queued background says about it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInfo {
+    /// Detected from the file contents, e.g. "png" or "jpeg".
+    pub format: String,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Settings of a single background which take the place of the ones of the rotation.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WallpaperMeta {
+    /// Seconds the background stays up, in place of the rotation interval.
+    pub duration: Option<u64>,
+    /// How often random mode picks the background, and how early in a cycle shuffle mode does,
+    /// relative to the default weight of 1.
+    pub weight: Option<u32>,
+    /// Whether the rotation stays on the background once it is shown, until switched by hand.
+    pub pinned: bool,
+    /// Phase of the sun the rotation shows the background in. Any phase if unset.
+    pub phase: Option<SolarPhase>,
+}
+
+/// A file which was left out of the queue.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// A wallpaper the backend failed to show.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedWallpaper {
+    pub path: String,
+    /// Failed attempts in a row.
+    pub failures: u32,
+    /// What the backend reported the last time, including the exit code.
+    pub error: String,
+    /// Whether the rotation passes over the wallpaper because it failed too often.
+    pub skipped: bool,
+}
+
+/// A wallpaper which was shown, on the first output if outputs show different wallpapers.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub path: String,
+    /// Seconds since the Unix epoch.
+    pub shown_at: u64,
+    pub reason: SwitchReason,
+}
+
+/// Snapshot of what the daemon is doing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// Version of the running daemon.
+    pub version: String,
+    /// Wallpaper the rotation showed last. With outputs showing different wallpapers, the one on
+    /// the first output.
+    pub wallpaper: Option<String>,
+    /// Queue index of `wallpaper`, unless it left the queue since.
+    pub index: Option<usize>,
+    /// Wallpaper shown at the next switch.
+    pub upcoming: Option<String>,
+    /// Name of the active playlist.
+    pub playlist: String,
+    /// Name of the schedule rule which applies right now.
+    pub rule: Option<String>,
+    pub mode: RotationMode,
+    pub output_mode: OutputMode,
+    /// Rotation interval in seconds.
+    pub interval: u64,
+    /// Seconds `wallpaper` stays up for: its own duration, or the interval.
+    pub duration: u64,
+    /// Seconds until the next switch. Stands still while paused.
+    pub next_switch: u64,
+    pub paused: bool,
+    pub backend: BackendStatus,
+}
+
+/// Whether the wallpaper backend can show wallpapers right now.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendStatus {
+    pub ready: bool,
+    /// Why the backend is not ready.
+    pub error: Option<String>,
+    /// Number of wallpapers skipped because they failed too often.
+    pub skipped: usize,
+}
+
+impl WallpaperMeta {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration == Some(0) {
+            return Err("Duration must be longer than zero".to_string());
+        }
+        if self.weight == Some(0) {
+            return Err("Weight must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Weight to pick the background with, 1 unless set.
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(1)
+    }
+
+    /// Whether the rotation may show the background while it is `phase` outside, or at a time
+    /// the phase is not known.
+    pub fn fits(&self, phase: Option<SolarPhase>) -> bool {
+        match (self.phase, phase) {
+            (Some(tagged), Some(phase)) => tagged == phase,
+            _ => true,
+        }
+    }
 }
 
 impl Request {
@@ -27,6 +301,58 @@ impl Request {
         let position = Position::try_from(position.as_str())?;
         Ok(Request::RearrangeBackground((bg, position, target_bg)))
     }
+
+    /// Accepts human friendly durations, see [`crate::duration::parse_duration`].
+    pub fn set_interval(interval: &str) -> Result<Request, &'static str> {
+        let interval = crate::duration::parse_duration(interval)?;
+        Ok(Request::SetInterval(interval.as_secs()))
+    }
+}
+
+mod error {
+    use super::*;
+
+    pub type ItchResult<T> = Result<T, ItchError>;
+
+    /// Errors reported by the daemon in place of a successful response.
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, thiserror::Error)]
+    #[serde(tag = "kind", content = "detail", rename_all = "camelCase")]
+    pub enum ItchError {
+        #[error("Background is not in queue: {0}")]
+        NotInQueue(String),
+        #[error("Target background is not in queue: {0}")]
+        TargetNotInQueue(String),
+        #[error("Refusing to move wallpaper to the same position")]
+        SamePosition,
+        #[error("swww failed: {0}")]
+        SwwwFailed(String),
+        #[error("Wallpaper backend failed: {0}")]
+        BackendFailed(String),
+        #[error("Invalid path: {0}")]
+        InvalidPath(String),
+        #[error("Scheduler is unavailable")]
+        SchedulerUnavailable,
+        #[error("Invalid interval: {0}")]
+        InvalidInterval(String),
+        #[error("Invalid wallpaper source: {0}")]
+        InvalidSource(String),
+        #[error("No such playlist: {0}")]
+        PlaylistNotFound(String),
+        #[error("Playlist already exists: {0}")]
+        PlaylistExists(String),
+        #[error("Invalid playlist name: {0:?}")]
+        InvalidPlaylistName(String),
+        #[error("Refusing to delete the active playlist: {0}")]
+        PlaylistActive(String),
+        #[error("No such output: {0}")]
+        UnknownOutput(String),
+        #[error("Invalid transition: {0}")]
+        InvalidTransition(String),
+        #[error("Invalid wallpaper settings: {0}")]
+        InvalidWallpaperMeta(String),
+        #[error("No earlier wallpaper to go back to")]
+        NoHistory,
+    }
 }
 
 mod rearrange {
@@ -60,3 +386,115 @@ mod rearrange {
         }
     }
 }
+
+mod rotation {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[serde(rename_all = "camelCase")]
+    pub enum RotationMode {
+        /// Walk the queue in order.
+        #[default]
+        Sequential,
+        /// Walk the queue in a random order which is reshuffled once every wallpaper was shown.
+        Shuffle,
+        /// Pick any wallpaper which was not shown recently.
+        Random,
+        /// Walk the queue backwards.
+        Reverse,
+    }
+
+    impl TryFrom<&str> for RotationMode {
+        type Error = &'static str;
+
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            match value {
+                "sequential" => Ok(RotationMode::Sequential),
+                "shuffle" => Ok(RotationMode::Shuffle),
+                "random" => Ok(RotationMode::Random),
+                "reverse" => Ok(RotationMode::Reverse),
+                _ => Err("Mode must be 'sequential', 'shuffle', 'random' or 'reverse'"),
+            }
+        }
+    }
+
+    impl std::fmt::Display for RotationMode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                RotationMode::Sequential => write!(f, "sequential"),
+                RotationMode::Shuffle => write!(f, "shuffle"),
+                RotationMode::Random => write!(f, "random"),
+                RotationMode::Reverse => write!(f, "reverse"),
+            }
+        }
+    }
+}
+
+mod history {
+    use super::*;
+
+    /// Why a wallpaper was shown.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "camelCase")]
+    pub enum SwitchReason {
+        /// The interval ran out.
+        Scheduled,
+        /// Switched to over IPC.
+        Manual,
+        /// The wallpaper given on the command line or in the config when the daemon started.
+        Startup,
+        /// A switch time of the active schedule rule came.
+        Rule,
+    }
+
+    impl std::fmt::Display for SwitchReason {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                SwitchReason::Scheduled => write!(f, "scheduled"),
+                SwitchReason::Manual => write!(f, "manual"),
+                SwitchReason::Startup => write!(f, "startup"),
+                SwitchReason::Rule => write!(f, "rule"),
+            }
+        }
+    }
+}
+
+mod output {
+    use super::*;
+
+    /// How the queue is spread over multiple outputs.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    #[serde(rename_all = "camelCase")]
+    pub enum OutputMode {
+        /// Show the same wallpaper on every output.
+        #[default]
+        Same,
+        /// Give every output its own position in the queue.
+        Independent,
+        /// Show consecutive wallpapers side by side, one per output.
+        Spanned,
+    }
+
+    impl TryFrom<&str> for OutputMode {
+        type Error = &'static str;
+
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            match value {
+                "same" => Ok(OutputMode::Same),
+                "independent" => Ok(OutputMode::Independent),
+                "spanned" => Ok(OutputMode::Spanned),
+                _ => Err("Output mode must be 'same', 'independent' or 'spanned'"),
+            }
+        }
+    }
+
+    impl std::fmt::Display for OutputMode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                OutputMode::Same => write!(f, "same"),
+                OutputMode::Independent => write!(f, "independent"),
+                OutputMode::Spanned => write!(f, "spanned"),
+            }
+        }
+    }
+}
@@ -0,0 +1,682 @@
+//! Rules which pick the playlist and the switch times by the time of day.
+
+use std::fmt;
+
+use chrono::{
+    DateTime, Datelike, Days as DayCount, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Timelike,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sun::{Location, SolarPhase};
+
+/// How far ahead [`Cron::next_after`] looks. Covers every day of month on every weekday, and
+/// leap days.
+const CRON_SEARCH_DAYS: u64 = 8 * 366;
+
+/// A `[[schedule]]` table. The first rule which applies decides what is played.
+///
+/// ```toml
+/// [[schedule]]
+/// from = "07:00"
+/// to = "18:00"
+/// days = "weekdays"
+/// playlist = "day"
+///
+/// [[schedule]]
+/// name = "evening"
+/// from = "18:00"
+/// to = "07:00"
+/// playlist = "evening"
+/// at = "0 */2 * * *"
+///
+/// [[schedule]]
+/// when = "night"
+/// playlist = "dark"
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ScheduleRule {
+    /// Shown in the status. Defaults to a description of when the rule applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Start of the daily window the rule applies in. A window ending before it starts runs
+    /// past midnight. Without `from` and `to` the rule applies all day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<TimeOfDay>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<TimeOfDay>,
+    /// Days on which the window starts. Every day if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<Days>,
+    /// How light it has to be outside at the configured [`Location`]. Any time if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<SolarPhase>,
+    /// Playlist to play while the rule applies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playlist: Option<String>,
+    /// Times to switch at while the rule applies, in place of the interval.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at: Option<Cron>,
+}
+
+impl ScheduleRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.from.is_some() != self.to.is_some() {
+            return Err(format!(
+                "{}: Set both 'from' and 'to', or neither",
+                self.label()
+            ));
+        }
+        if self.playlist.is_none() && self.at.is_none() {
+            return Err(format!("{}: Set 'playlist', 'at' or both", self.label()));
+        }
+        Ok(())
+    }
+
+    /// The name of the rule, or when it applies.
+    pub fn label(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+
+        let mut parts = vec![];
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            parts.push(format!("{from}-{to}"));
+        }
+        if let Some(days) = self.days {
+            parts.push(days.to_string());
+        }
+        if let Some(when) = self.when {
+            parts.push(when.to_string());
+        }
+        if let Some(at) = &self.at {
+            parts.push(format!("at {at}"));
+        }
+        parts.join(" ")
+    }
+
+    /// Whether the rule applies at the local time `now`, when it is `phase` outside.
+    pub fn applies_at(&self, now: NaiveDateTime, phase: Option<SolarPhase>) -> bool {
+        if self.when.is_some() && self.when != phase {
+            return false;
+        }
+
+        let today = now.weekday().num_days_from_monday();
+        let yesterday = (today + 6) % 7;
+        let on = |day| self.days.is_none_or(|days| days.contains(day));
+
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => {
+                let time = TimeOfDay::from(now.time());
+                if from < to {
+                    on(today) && from <= time && time < to
+                } else {
+                    // The window runs past midnight and belongs to the day it started on.
+                    (on(today) && time >= from) || (on(yesterday) && time < to)
+                }
+            }
+            _ => on(today),
+        }
+    }
+}
+
+/// Schedule rules along with the location the sun is followed at.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    rules: Vec<ScheduleRule>,
+    location: Option<Location>,
+}
+
+impl Schedule {
+    pub fn new(rules: Vec<ScheduleRule>, location: Option<Location>) -> Result<Self, String> {
+        for rule in &rules {
+            rule.validate()?;
+            if rule.when.is_some() && location.is_none() {
+                return Err(format!(
+                    "{}: Set a [location] to follow the sun",
+                    rule.label()
+                ));
+            }
+        }
+        if let Some(location) = location {
+            location.validate()?;
+        }
+
+        Ok(Self { rules, location })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether a location is set, so the sun can be followed.
+    pub fn follows_sun(&self) -> bool {
+        self.location.is_some()
+    }
+
+    /// How light it is outside at `now`, if a location is set.
+    pub fn phase(&self, now: DateTime<FixedOffset>) -> Option<SolarPhase> {
+        self.location.map(|location| location.phase(now.to_utc()))
+    }
+
+    /// The next time after `now` at which the phase changes, if a location is set and it ever
+    /// does.
+    pub fn next_phase_change(&self, now: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        self.location
+            .and_then(|location| location.next_phase_change(now.to_utc()))
+            .map(|next| next.with_timezone(now.offset()))
+    }
+
+    /// The first rule which applies at `now`, along with its position.
+    pub fn active_rule(&self, now: DateTime<FixedOffset>) -> Option<(usize, &ScheduleRule)> {
+        let phase = self.phase(now);
+
+        self.rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.applies_at(now.naive_local(), phase))
+    }
+
+    /// The next time after `now` at which a different rule may start to apply, if ever.
+    pub fn next_change(&self, now: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        let mut times: Vec<TimeOfDay> = self
+            .rules
+            .iter()
+            .flat_map(|rule| [rule.from, rule.to])
+            .flatten()
+            .collect();
+        if self.rules.iter().any(|rule| rule.days.is_some()) {
+            times.push(TimeOfDay(0));
+        }
+
+        let local = now.naive_local();
+        let windows = times.into_iter().filter_map(|time| {
+            let today = local.date().and_time(time.into());
+            let next = match today > local {
+                true => today,
+                false => today + DayCount::new(1),
+            };
+            now.offset().from_local_datetime(&next).single()
+        });
+
+        let sun = match self.rules.iter().any(|rule| rule.when.is_some()) {
+            true => self.next_phase_change(now),
+            false => None,
+        };
+
+        windows.chain(sun).min()
+    }
+}
+
+/// A time of day with minute precision, written as "HH:MM".
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u16);
+
+impl TryFrom<&str> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid time of day {value:?}, expected HH:MM");
+
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours: u16 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        TimeOfDay::try_from(value.as_str())
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl From<NaiveTime> for TimeOfDay {
+    fn from(time: NaiveTime) -> Self {
+        TimeOfDay((time.hour() * 60 + time.minute()) as u16)
+    }
+}
+
+impl From<TimeOfDay> for NaiveTime {
+    fn from(time: TimeOfDay) -> Self {
+        NaiveTime::from_hms_opt((time.0 / 60).into(), (time.0 % 60).into(), 0)
+            .expect("TimeOfDay should always be a valid time")
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+const DAY_NAMES: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+const WEEKDAYS: u8 = 0b0011111;
+const WEEKENDS: u8 = 0b1100000;
+
+/// A set of days of the week, written as "weekdays", "weekends" or a list of day names like
+/// `["mon", "wed"]`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "DaysRepr", into = "DaysRepr")]
+pub struct Days(u8);
+
+impl Days {
+    /// Whether the day counted from Monday is in the set.
+    fn contains(self, day: u32) -> bool {
+        self.0 & (1 << day) != 0
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DaysRepr {
+    Named(String),
+    List(Vec<String>),
+}
+
+impl TryFrom<DaysRepr> for Days {
+    type Error = String;
+
+    fn try_from(repr: DaysRepr) -> Result<Self, Self::Error> {
+        match repr {
+            DaysRepr::Named(name) => match name.as_str() {
+                "weekdays" => Ok(Days(WEEKDAYS)),
+                "weekends" => Ok(Days(WEEKENDS)),
+                _ => Days::try_from(DaysRepr::List(vec![name])),
+            },
+            DaysRepr::List(names) => names.iter().try_fold(Days(0), |days, name| {
+                let day = day_number(name).ok_or(format!(
+                    "Invalid day {name:?}, expected weekdays, weekends or a day like mon or monday"
+                ))?;
+                Ok(Days(days.0 | 1 << day))
+            }),
+        }
+    }
+}
+
+impl From<Days> for DaysRepr {
+    fn from(days: Days) -> Self {
+        match days.0 {
+            WEEKDAYS => DaysRepr::Named("weekdays".to_string()),
+            WEEKENDS => DaysRepr::Named("weekends".to_string()),
+            _ => DaysRepr::List(
+                (0..7)
+                    .filter(|day| days.contains(*day))
+                    .map(|day| DAY_NAMES[day as usize][..3].to_string())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Days {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match DaysRepr::from(*self) {
+            DaysRepr::Named(name) => write!(f, "{name}"),
+            DaysRepr::List(names) => write!(f, "{}", names.join(",")),
+        }
+    }
+}
+
+/// Day of the week counted from Monday, from an abbreviated or full English name.
+fn day_number(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    DAY_NAMES
+        .iter()
+        .position(|day| name == *day || name == day[..3])
+        .map(|day| day as u32)
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const CRON_DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A cron expression with the fields "minute hour day-of-month month day-of-week", e.g.
+/// "30 7 * * mon-fri". Fields take `*`, numbers, names, ranges, lists and steps. Like cron, a
+/// time matches if either day field matches when both are restricted.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    /// Counted from Sunday.
+    days_of_week: u8,
+    /// Whether the day fields were `*`.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl Cron {
+    /// The first matching minute after `now`.
+    pub fn next_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = now.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+
+        let mut date = start.date();
+        let mut from = (start.hour(), start.minute());
+        for _ in 0..CRON_SEARCH_DAYS {
+            if self.matches_date(date) {
+                for hour in (from.0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                    let first = if hour == from.0 { from.1 } else { 0 };
+                    if let Some(minute) =
+                        (first..60).find(|minute| self.minutes & (1 << minute) != 0)
+                    {
+                        return date.and_hms_opt(hour, minute, 0);
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+            from = (0, 0);
+        }
+        None
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (false, true) => day_of_month,
+            (true, false) => day_of_week,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+}
+
+impl TryFrom<&str> for Cron {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(format!(
+                "Invalid cron expression {value:?}, expected 5 fields: minute hour day-of-month month day-of-week"
+            ));
+        };
+
+        let cron = Cron {
+            source: fields.join(" "),
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])? as u32,
+            days_of_month: parse_field(days_of_month, 1, 31, &[])? as u32,
+            months: parse_field(months, 1, 12, &MONTH_NAMES)? as u16,
+            // 7 is Sunday too.
+            days_of_week: {
+                let days = parse_field(days_of_week, 0, 7, &CRON_DAY_NAMES)?;
+                (days | days >> 7) as u8 & 0b1111111
+            },
+            any_day_of_month: days_of_month == "*",
+            any_day_of_week: days_of_week == "*",
+        };
+
+        // Catch dates which never come, like the 31st of February.
+        let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+            .expect("2000-01-01 should be a valid date");
+        if cron.next_after(epoch).is_none() {
+            return Err(format!("Cron expression {value:?} never matches"));
+        }
+
+        Ok(cron)
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Cron::try_from(value.as_str())
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.source
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Parse a comma separated list of `*`, values and ranges, each with an optional `/step`, into
+/// a bit set. `names` are accepted in place of the values starting from `min`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_lowercase();
+        if let Some(i) = names.iter().position(|name| *name == lower) {
+            return Ok(min + i as u32);
+        }
+        match s.parse() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(format!(
+                "Invalid cron value {s:?}, expected {min}-{max}{}",
+                match names.is_empty() {
+                    true => String::new(),
+                    false => format!(" or one of {}", names.join(", ")),
+                }
+            )),
+        }
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("Invalid cron step {step:?}")),
+            },
+            None => (part, 1),
+        };
+
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // A single value with a step runs to the end, like cron.
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if first > last {
+            return Err(format!("Invalid cron range {range:?}"));
+        }
+
+        for n in (first..=last).step_by(step) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn rule(toml: &str) -> ScheduleRule {
+        toml::from_str(&format!("playlist = \"p\"\n{toml}")).unwrap()
+    }
+
+    fn cron(expr: &str) -> Cron {
+        Cron::try_from(expr).unwrap()
+    }
+
+    /// The next `count` times `cron` matches after `from`.
+    fn next(cron: &Cron, from: &str, count: usize) -> Vec<String> {
+        std::iter::successors(cron.next_after(at(from)), |last| cron.next_after(*last))
+            .take(count)
+            .map(|time| time.format("%a %Y-%m-%d %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn windows_run_past_midnight() {
+        // 2025-03-21 is a Friday.
+        let night = rule("from = \"22:00\"\nto = \"06:00\"");
+        assert!(!night.applies_at(at("2025-03-21 21:59"), None));
+        assert!(night.applies_at(at("2025-03-21 22:00"), None));
+        assert!(night.applies_at(at("2025-03-22 00:00"), None));
+        assert!(night.applies_at(at("2025-03-22 05:59"), None));
+        assert!(!night.applies_at(at("2025-03-22 06:00"), None));
+
+        let day = rule("from = \"06:00\"\nto = \"22:00\"");
+        assert!(day.applies_at(at("2025-03-21 06:00"), None));
+        assert!(!day.applies_at(at("2025-03-21 22:00"), None));
+    }
+
+    #[test]
+    fn windows_belong_to_the_day_they_start_on() {
+        // Friday night runs into Saturday morning, Sunday night into Monday morning.
+        let weekday_nights = rule("from = \"22:00\"\nto = \"06:00\"\ndays = \"weekdays\"");
+        assert!(weekday_nights.applies_at(at("2025-03-21 23:00"), None));
+        assert!(weekday_nights.applies_at(at("2025-03-22 05:00"), None));
+        assert!(!weekday_nights.applies_at(at("2025-03-22 23:00"), None));
+        assert!(!weekday_nights.applies_at(at("2025-03-24 05:00"), None));
+        assert!(weekday_nights.applies_at(at("2025-03-24 23:00"), None));
+
+        let weekends = rule("days = \"weekends\"");
+        assert!(!weekends.applies_at(at("2025-03-21 12:00"), None));
+        assert!(weekends.applies_at(at("2025-03-22 00:00"), None));
+        assert!(weekends.applies_at(at("2025-03-23 23:59"), None));
+        assert!(!weekends.applies_at(at("2025-03-24 00:00"), None));
+
+        let some_days = rule("days = [\"mon\", \"Friday\"]");
+        assert!(some_days.applies_at(at("2025-03-21 12:00"), None));
+        assert!(!some_days.applies_at(at("2025-03-22 12:00"), None));
+        assert!(some_days.applies_at(at("2025-03-24 12:00"), None));
+    }
+
+    #[test]
+    fn follows_the_solar_phase() {
+        let day = rule("when = \"day\"");
+        assert!(day.applies_at(at("2025-03-21 12:00"), Some(SolarPhase::Day)));
+        assert!(!day.applies_at(at("2025-03-21 12:00"), Some(SolarPhase::Twilight)));
+        assert!(!day.applies_at(at("2025-03-21 12:00"), None));
+    }
+
+    #[test]
+    fn cron_steps_through_ranges() {
+        assert_eq!(
+            next(&cron("*/20 9-10 * * *"), "2025-03-21 09:05", 5),
+            [
+                "Fri 2025-03-21 09:20",
+                "Fri 2025-03-21 09:40",
+                "Fri 2025-03-21 10:00",
+                "Fri 2025-03-21 10:20",
+                "Fri 2025-03-21 10:40",
+            ]
+        );
+        assert_eq!(
+            next(&cron("0 6-18/6 * * *"), "2025-03-21 12:00", 3),
+            [
+                "Fri 2025-03-21 18:00",
+                "Sat 2025-03-22 06:00",
+                "Sat 2025-03-22 12:00"
+            ]
+        );
+        // A single value with a step runs to the end of the field.
+        assert_eq!(
+            next(&cron("0 20/2 * * *"), "2025-03-21 00:00", 3),
+            [
+                "Fri 2025-03-21 20:00",
+                "Fri 2025-03-21 22:00",
+                "Sat 2025-03-22 20:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn cron_takes_day_and_month_names() {
+        assert_eq!(
+            next(&cron("30 7 * * MON-wed"), "2025-03-21 00:00", 4),
+            [
+                "Mon 2025-03-24 07:30",
+                "Tue 2025-03-25 07:30",
+                "Wed 2025-03-26 07:30",
+                "Mon 2025-03-31 07:30",
+            ]
+        );
+        assert_eq!(
+            next(&cron("0 0 1 jan,jul *"), "2025-03-21 00:00", 2),
+            ["Tue 2025-07-01 00:00", "Thu 2026-01-01 00:00"]
+        );
+        // Both 0 and 7 are Sunday.
+        let friday = at("2025-03-21 00:00");
+        assert_eq!(
+            cron("0 0 * * 7").next_after(friday),
+            cron("0 0 * * sun").next_after(friday)
+        );
+    }
+
+    #[test]
+    fn cron_matches_either_day_field_when_both_are_set() {
+        // The 25th, which is a Tuesday, and every Sunday.
+        assert_eq!(
+            next(&cron("0 12 25 * sun"), "2025-03-21 00:00", 4),
+            [
+                "Sun 2025-03-23 12:00",
+                "Tue 2025-03-25 12:00",
+                "Sun 2025-03-30 12:00",
+                "Sun 2025-04-06 12:00",
+            ]
+        );
+        // With either one left at `*` only the other one counts.
+        assert_eq!(
+            next(&cron("0 12 25 * *"), "2025-03-21 00:00", 2),
+            ["Tue 2025-03-25 12:00", "Fri 2025-04-25 12:00"]
+        );
+    }
+
+    #[test]
+    fn cron_rejects_invalid_and_impossible_expressions() {
+        assert!(Cron::try_from("0 0 31 2 *").is_err());
+        assert!(Cron::try_from("0 0 30 feb *").is_err());
+        assert!(Cron::try_from("0 0 29 2 *").is_ok());
+        assert!(Cron::try_from("0 0 * *").is_err());
+        assert!(Cron::try_from("60 * * * *").is_err());
+        assert!(Cron::try_from("*/0 * * * *").is_err());
+        assert!(Cron::try_from("0 10-8 * * *").is_err());
+        assert!(Cron::try_from("0 0 * * fri-mon").is_err());
+    }
+
+    #[test]
+    fn cron_finds_the_next_leap_day() {
+        assert_eq!(
+            next(&cron("0 0 29 2 *"), "2025-03-21 00:00", 2),
+            ["Tue 2028-02-29 00:00", "Sun 2032-02-29 00:00"]
+        );
+    }
+}
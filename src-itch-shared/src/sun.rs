@@ -0,0 +1,194 @@
+//! Where the sun stands, worked out locally from the date and a location.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Altitude of the sun's centre at sunrise and sunset, allowing for refraction and its radius.
+const SUNRISE_ALTITUDE: f64 = -0.833;
+/// Altitude of the sun's centre at the start of dawn and the end of dusk.
+const CIVIL_TWILIGHT_ALTITUDE: f64 = -6.0;
+/// How far ahead [`Location::next_phase_change`] looks, long enough to cover a day near the
+/// polar circles.
+const PHASE_SEARCH: Duration = Duration::hours(48);
+
+/// A place on earth, written as a `[location]` table.
+///
+/// ```toml
+/// [location]
+/// latitude = 52.52
+/// longitude = 13.40
+/// ```
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Location {
+    /// Degrees north of the equator, negative in the south.
+    pub latitude: f64,
+    /// Degrees east of Greenwich, negative in the west.
+    pub longitude: f64,
+}
+
+/// How light it is outside.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SolarPhase {
+    /// From sunrise until sunset.
+    Day,
+    /// Civil twilight, from dawn until sunrise and from sunset until dusk.
+    Twilight,
+    /// From dusk until dawn.
+    Night,
+}
+
+impl TryFrom<&str> for SolarPhase {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "day" => Ok(SolarPhase::Day),
+            "twilight" => Ok(SolarPhase::Twilight),
+            "night" => Ok(SolarPhase::Night),
+            _ => Err("Phase must be 'day', 'twilight' or 'night'"),
+        }
+    }
+}
+
+impl fmt::Display for SolarPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolarPhase::Day => write!(f, "day"),
+            SolarPhase::Twilight => write!(f, "twilight"),
+            SolarPhase::Night => write!(f, "night"),
+        }
+    }
+}
+
+impl Location {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            return Err(format!(
+                "Latitude must be between -90 and 90, got {}",
+                self.latitude
+            ));
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(format!(
+                "Longitude must be between -180 and 180, got {}",
+                self.longitude
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn phase(&self, at: DateTime<Utc>) -> SolarPhase {
+        let altitude = self.sun_altitude(at);
+        if altitude >= SUNRISE_ALTITUDE {
+            SolarPhase::Day
+        } else if altitude >= CIVIL_TWILIGHT_ALTITUDE {
+            SolarPhase::Twilight
+        } else {
+            SolarPhase::Night
+        }
+    }
+
+    /// The first minute after `at` in a different phase. `None` during polar day or night.
+    pub fn next_phase_change(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let phase = self.phase(at);
+
+        (1..=PHASE_SEARCH.num_minutes())
+            .map(|minutes| at + Duration::minutes(minutes))
+            .find(|later| self.phase(*later) != phase)
+    }
+
+    /// Degrees the sun stands above the horizon, good to about a minute of time.
+    ///
+    /// Uses the low precision formulas of the Astronomical Almanac.
+    fn sun_altitude(&self, at: DateTime<Utc>) -> f64 {
+        // Days since J2000.0.
+        let n = at.timestamp() as f64 / 86400.0 + 2440587.5 - 2451545.0;
+
+        let mean_longitude = (280.460 + 0.9856474 * n).rem_euclid(360.0);
+        let mean_anomaly = (357.528 + 0.9856003 * n).rem_euclid(360.0).to_radians();
+        let ecliptic_longitude =
+            (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2.0 * mean_anomaly).sin())
+                .to_radians();
+        let obliquity = (23.439 - 0.0000004 * n).to_radians();
+
+        let right_ascension = (obliquity.cos() * ecliptic_longitude.sin())
+            .atan2(ecliptic_longitude.cos())
+            .to_degrees();
+        let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+
+        let sidereal_time = (280.46061837 + 360.98564736629 * n).rem_euclid(360.0);
+        let hour_angle = (sidereal_time + self.longitude - right_ascension).to_radians();
+        let latitude = self.latitude.to_radians();
+
+        (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+            .asin()
+            .to_degrees()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BERLIN: Location = Location {
+        latitude: 52.52,
+        longitude: 13.40,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.65,
+        longitude: 18.96,
+    };
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: &str) {
+        let actual = actual.unwrap();
+        let off = (actual - utc(expected)).num_minutes().abs();
+        assert!(off <= 2, "expected about {expected}, got {actual}");
+    }
+
+    #[test]
+    fn follows_the_sun_in_berlin_on_the_equinox() {
+        // Dawn 04:35, sunrise 05:09, sunset 17:20 and dusk 17:54 UTC by the NOAA calculator.
+        let midnight = utc("2025-03-20T00:00:00Z");
+        assert_eq!(BERLIN.phase(midnight), SolarPhase::Night);
+
+        let dawn = BERLIN.next_phase_change(midnight);
+        assert_near(dawn, "2025-03-20T04:35:00Z");
+        assert_eq!(BERLIN.phase(dawn.unwrap()), SolarPhase::Twilight);
+
+        let sunrise = BERLIN.next_phase_change(dawn.unwrap());
+        assert_near(sunrise, "2025-03-20T05:09:00Z");
+        assert_eq!(BERLIN.phase(sunrise.unwrap()), SolarPhase::Day);
+
+        let sunset = BERLIN.next_phase_change(sunrise.unwrap());
+        assert_near(sunset, "2025-03-20T17:20:00Z");
+        assert_eq!(BERLIN.phase(sunset.unwrap()), SolarPhase::Twilight);
+
+        let dusk = BERLIN.next_phase_change(sunset.unwrap());
+        assert_near(dusk, "2025-03-20T17:54:00Z");
+        assert_eq!(BERLIN.phase(dusk.unwrap()), SolarPhase::Night);
+    }
+
+    #[test]
+    fn never_changes_during_polar_day_or_night() {
+        let midsummer = utc("2025-06-21T12:00:00Z");
+        assert_eq!(TROMSO.phase(midsummer), SolarPhase::Day);
+        assert_eq!(TROMSO.next_phase_change(midsummer), None);
+
+        // Tromsø still gets civil twilight at midday in December, the far north does not.
+        let midwinter = utc("2025-12-21T12:00:00Z");
+        let north_pole = Location {
+            latitude: 89.0,
+            longitude: 0.0,
+        };
+        assert_eq!(north_pole.phase(midwinter), SolarPhase::Night);
+        assert_eq!(north_pole.next_phase_change(midwinter), None);
+    }
+}
@@ -1,17 +1,341 @@
+use serde::{Deserialize, Serialize};
+use std::process::Output;
+
 use tokio::process::Command;
 
-pub async fn set_background(path: &str) -> bool {
-    Command::new("swww")
-        .args([
-            "img",
-            path,
-            "--transition-fps",
-            "60",
-            "--transition-type",
-            "any",
-        ])
-        .status()
+use crate::message::OutputInfo;
+
+/// Transition types `swww img --transition-type` accepts.
+pub const TRANSITION_TYPES: &[&str] = &[
+    "none", "simple", "fade", "left", "right", "top", "bottom", "wipe", "wave", "grow", "center",
+    "any", "outer", "random",
+];
+
+/// Arguments controlling how `swww img` transitions to a new wallpaper. Options left unset
+/// fall back to the defaults of swww.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case", default)]
+pub struct TransitionOptions {
+    #[serde(rename = "type")]
+    pub transition_type: String,
+    /// How much the image changes per frame, 1 to 255.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<u8>,
+    /// Length of the transition in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f32>,
+    /// Direction of the wipe and wave transitions in degrees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub angle: Option<f32>,
+    /// Where the grow and outer transitions start, e.g. "center", "top-left" or "0.5,0.8".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+    /// Easing curve as four comma separated numbers, e.g. ".54,0,.34,.99".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bezier: Option<String>,
+    /// Width and height of the waves of the wave transition, e.g. "20,20".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wave: Option<String>,
+    pub fps: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resize: Option<ResizeMode>,
+    /// Hex color padding images which are resized to fit, e.g. "000000".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_color: Option<String>,
+}
+
+/// How `swww img` fits an image whose size differs from the output.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    /// Fill the output, cutting off what does not fit.
+    Crop,
+    /// Show the whole image, padding the rest with the fill color.
+    Fit,
+    /// Keep the image size, padding the rest with the fill color.
+    No,
+}
+
+impl Default for TransitionOptions {
+    fn default() -> Self {
+        Self {
+            transition_type: "any".to_string(),
+            step: None,
+            duration: None,
+            angle: None,
+            position: None,
+            bezier: None,
+            wave: None,
+            fps: 60,
+            resize: None,
+            fill_color: None,
+        }
+    }
+}
+
+impl TransitionOptions {
+    /// Catches values swww would refuse before a wallpaper is applied with them.
+    pub fn validate(&self) -> Result<(), String> {
+        if !TRANSITION_TYPES.contains(&self.transition_type.as_str()) {
+            return Err(format!(
+                "Unknown transition type {:?}, expected one of {}",
+                self.transition_type,
+                TRANSITION_TYPES.join(", ")
+            ));
+        }
+        if self.step == Some(0) {
+            return Err("Transition step must be at least 1".to_string());
+        }
+        if self.fps == 0 {
+            return Err("Transition fps must be at least 1".to_string());
+        }
+        if self
+            .duration
+            .is_some_and(|duration| duration.is_nan() || duration < 0.0)
+        {
+            return Err("Transition duration must not be negative".to_string());
+        }
+        if let Some(bezier) = &self.bezier {
+            numbers(bezier, 4).ok_or(format!("Bezier must be four numbers, got {bezier:?}"))?;
+        }
+        if let Some(wave) = &self.wave {
+            numbers(wave, 2).ok_or(format!("Wave must be two numbers, got {wave:?}"))?;
+        }
+        if let Some(color) = &self.fill_color {
+            let color = color.trim_start_matches('#');
+            if color.len() != 6 || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "Fill color must be a hex color like 000000, got {color:?}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--transition-fps".to_string(),
+            self.fps.to_string(),
+            "--transition-type".to_string(),
+            self.transition_type.clone(),
+        ];
+
+        let optional = [
+            ("--transition-step", self.step.map(|step| step.to_string())),
+            (
+                "--transition-duration",
+                self.duration.map(|duration| duration.to_string()),
+            ),
+            (
+                "--transition-angle",
+                self.angle.map(|angle| angle.to_string()),
+            ),
+            ("--transition-pos", self.position.clone()),
+            ("--transition-bezier", self.bezier.clone()),
+            ("--transition-wave", self.wave.clone()),
+            ("--resize", self.resize.map(|resize| resize.to_string())),
+            (
+                "--fill-color",
+                self.fill_color
+                    .as_ref()
+                    .map(|color| color.trim_start_matches('#').to_string()),
+            ),
+        ];
+        for (flag, value) in optional {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value);
+            }
+        }
+
+        args
+    }
+}
+
+impl TryFrom<&str> for ResizeMode {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "crop" => Ok(ResizeMode::Crop),
+            "fit" => Ok(ResizeMode::Fit),
+            "no" => Ok(ResizeMode::No),
+            _ => Err("Resize mode must be 'crop', 'fit' or 'no'"),
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeMode::Crop => write!(f, "crop"),
+            ResizeMode::Fit => write!(f, "fit"),
+            ResizeMode::No => write!(f, "no"),
+        }
+    }
+}
+
+/// Parses `count` comma separated numbers.
+fn numbers(s: &str, count: usize) -> Option<Vec<f32>> {
+    let numbers = s
+        .split(',')
+        .map(|n| n.trim().parse::<f32>().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    (numbers.len() == count).then_some(numbers)
+}
+
+/// Show `path` on `outputs`, or on every output if empty. Fails with what swww printed.
+pub async fn set_background(
+    path: &str,
+    outputs: &[String],
+    transition: &TransitionOptions,
+) -> Result<(), String> {
+    let mut command = Command::new("swww");
+    command.args(["img", path]);
+    if !outputs.is_empty() {
+        command.args(["--outputs", &outputs.join(",")]);
+    }
+
+    let output = command
+        .args(transition.args())
+        .output()
+        .await
+        .map_err(|err| format!("Could not run swww img: {err}"))?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(describe_failure("swww img", &output)),
+    }
+}
+
+/// Outputs swww-daemon is drawing on, in the order it lists them.
+pub async fn query_outputs() -> Result<Vec<OutputInfo>, String> {
+    let output = Command::new("swww")
+        .arg("query")
+        .output()
         .await
-        .map(|status| status.success())
-        .unwrap_or(false)
+        .map_err(|err| format!("Could not run swww query: {err}"))?;
+
+    if !output.status.success() {
+        return Err(describe_failure("swww query", &output));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_query_line)
+        .collect())
+}
+
+/// Lines look like `eDP-1: 1920x1080, scale: 1, currently displaying: image: /path`. Newer
+/// versions of swww prefix them with `: `.
+fn parse_query_line(line: &str) -> Option<OutputInfo> {
+    let line = line.trim().trim_start_matches(':').trim_start();
+    let (name, rest) = line.split_once(':')?;
+
+    Some(OutputInfo {
+        name: name.trim().to_string(),
+        wallpaper: rest
+            .split_once("currently displaying: image: ")
+            .map(|(_, path)| path.trim().to_string()),
+    })
+}
+
+/// The exit code and whatever a failed command printed to stderr.
+pub fn describe_failure(command: &str, output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.trim() {
+        "" => format!("{command} failed with {}", output.status),
+        stderr => format!("{command} failed with {}: {stderr}", output.status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(name: &str, wallpaper: Option<&str>) -> Option<OutputInfo> {
+        Some(OutputInfo {
+            name: name.to_string(),
+            wallpaper: wallpaper.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn parses_query_lines() {
+        assert_eq!(
+            parse_query_line("eDP-1: 1920x1080, scale: 1, currently displaying: image: /a.png"),
+            output("eDP-1", Some("/a.png"))
+        );
+        assert_eq!(
+            parse_query_line(
+                ": DP-1: 2560x1440, scale: 1.5, currently displaying: image: /my walls/b.png\n"
+            ),
+            output("DP-1", Some("/my walls/b.png"))
+        );
+    }
+
+    #[test]
+    fn parses_query_lines_of_outputs_without_an_image() {
+        assert_eq!(
+            parse_query_line("HDMI-A-1: 1920x1080, scale: 1, currently displaying: color: 000000"),
+            output("HDMI-A-1", None)
+        );
+    }
+
+    #[test]
+    fn skips_query_lines_without_an_output() {
+        assert_eq!(parse_query_line(""), None);
+        assert_eq!(parse_query_line("swww-daemon is not running"), None);
+    }
+
+    #[test]
+    fn passes_only_the_options_which_are_set() {
+        assert_eq!(
+            TransitionOptions::default().args(),
+            ["--transition-fps", "60", "--transition-type", "any"]
+        );
+    }
+
+    #[test]
+    fn passes_every_option() {
+        let transition = TransitionOptions {
+            transition_type: "wave".to_string(),
+            step: Some(90),
+            duration: Some(1.5),
+            angle: Some(30.0),
+            position: Some("top-left".to_string()),
+            bezier: Some(".54,0,.34,.99".to_string()),
+            wave: Some("20,20".to_string()),
+            fps: 144,
+            resize: Some(ResizeMode::Fit),
+            fill_color: Some("#1e1e2e".to_string()),
+        };
+
+        assert_eq!(
+            transition.args(),
+            [
+                "--transition-fps",
+                "144",
+                "--transition-type",
+                "wave",
+                "--transition-step",
+                "90",
+                "--transition-duration",
+                "1.5",
+                "--transition-angle",
+                "30",
+                "--transition-pos",
+                "top-left",
+                "--transition-bezier",
+                ".54,0,.34,.99",
+                "--transition-wave",
+                "20,20",
+                "--resize",
+                "fit",
+                "--fill-color",
+                "1e1e2e",
+            ]
+        );
+    }
 }
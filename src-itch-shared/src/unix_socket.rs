@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
+mod framing;
 mod internal;
 mod setup;
 pub use self::{
-    internal::{Listener, UnixSocket},
+    framing::{FrameReader, write_frame},
+    internal::{
+        Connection, DEFAULT_CALL_TIMEOUT, Listener, RequestContext, Subscriber, UnixSocket,
+    },
     setup::{IntoUnixSocketPath, UnixSocketPath},
 };
 
@@ -0,0 +1,198 @@
+use std::io::{self, Read, Write};
+
+/// Messages are newline-delimited. `serde_json` never emits raw newlines in compact output,
+/// so a single `\n` unambiguously terminates a frame.
+const DELIMITER: u8 = b'\n';
+
+/// Streaming decoder splitting a byte stream into newline-delimited frames.
+///
+/// Handles frames spanning several reads as well as several frames arriving in a single read.
+pub struct FrameReader<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    /// Everything before this offset in `buf` has already been searched for a delimiter.
+    scanned: usize,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            scanned: 0,
+        }
+    }
+
+    /// Blocks until a complete frame is available.
+    ///
+    /// Returns `Ok(None)` once the peer has closed the stream. Any trailing bytes without a
+    /// delimiter are treated as a final frame so that peers which do not terminate their last
+    /// message are still understood.
+    pub fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut chunk = [0; 8 * 1024];
+
+        loop {
+            if let Some(frame) = self.take_buffered_frame() {
+                if frame.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Ok(Some(frame));
+            }
+
+            let n_bytes = match self.inner.read(&mut chunk) {
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            if n_bytes == 0 {
+                let rest = std::mem::take(&mut self.buf);
+                self.scanned = 0;
+
+                if rest.iter().all(u8::is_ascii_whitespace) {
+                    return Ok(None);
+                }
+                return Ok(Some(rest));
+            }
+
+            self.buf.extend_from_slice(&chunk[..n_bytes]);
+        }
+    }
+
+    fn take_buffered_frame(&mut self) -> Option<Vec<u8>> {
+        let offset = self.buf[self.scanned..]
+            .iter()
+            .position(|b| *b == DELIMITER);
+
+        match offset {
+            Some(offset) => {
+                let end = self.scanned + offset;
+                let mut frame: Vec<u8> = self.buf.drain(..=end).collect();
+                frame.pop();
+                self.scanned = 0;
+                Some(frame)
+            }
+            None => {
+                self.scanned = self.buf.len();
+                None
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Writes `frame` followed by the delimiter as a single write.
+pub fn write_frame<W: Write>(to: &mut W, frame: &[u8]) -> io::Result<()> {
+    debug_assert!(
+        !frame.contains(&DELIMITER),
+        "frames must not contain the delimiter"
+    );
+
+    let mut data = Vec::with_capacity(frame.len() + 1);
+    data.extend_from_slice(frame);
+    data.push(DELIMITER);
+
+    to.write_all(&data)?;
+    to.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Hands out the given chunks one read at a time, like a socket would.
+    struct Chunks(VecDeque<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(mut chunk) = self.0.pop_front() else {
+                return Ok(0);
+            };
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.0.push_front(chunk.split_off(n));
+            }
+            Ok(n)
+        }
+    }
+
+    fn frames(chunks: Vec<&[u8]>) -> Vec<Vec<u8>> {
+        let chunks = chunks.into_iter().map(<[u8]>::to_vec).collect();
+        FrameReader::new(Chunks(chunks))
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_a_multi_megabyte_queue() {
+        let queue: Vec<String> = (0..100_000)
+            .map(|i| format!("/home/user/backgrounds/wallpaper-{i:06}.png"))
+            .collect();
+        let payload = serde_json::to_vec(&queue).unwrap();
+        assert!(payload.len() > 4 * 1024 * 1024);
+
+        // Through a real socket, which hands the payload over in many partial reads.
+        let (mut tx, rx) = std::os::unix::net::UnixStream::pair().unwrap();
+        let writer = std::thread::spawn(move || {
+            write_frame(&mut tx, &payload).unwrap();
+            write_frame(&mut tx, b"{}").unwrap();
+        });
+
+        let mut reader = FrameReader::new(rx);
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<String>>(&frame).unwrap(),
+            queue
+        );
+        assert_eq!(reader.read_frame().unwrap().unwrap(), b"{}");
+        writer.join().unwrap();
+        assert_eq!(reader.read_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn splits_several_frames_from_one_read() {
+        assert_eq!(
+            frames(vec![b"{\"a\":1}\n{\"b\":2}\n\n{\"c\":3}\n"]),
+            vec![
+                b"{\"a\":1}".to_vec(),
+                b"{\"b\":2}".to_vec(),
+                b"{\"c\":3}".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_a_frame_split_across_reads() {
+        assert_eq!(
+            frames(vec![b"{\"qu", b"eue\":[", b"]}\n{\"x\"", b":1}\n"]),
+            vec![b"{\"queue\":[]}".to_vec(), b"{\"x\":1}".to_vec()]
+        );
+    }
+
+    #[test]
+    fn treats_bytes_before_eof_as_a_final_frame() {
+        let mut reader = FrameReader::new(Chunks(VecDeque::from([
+            b"{\"a\":1}\n{\"b\"".to_vec(),
+            b":2".to_vec(),
+        ])));
+
+        assert_eq!(reader.read_frame().unwrap().unwrap(), b"{\"a\":1}");
+        assert_eq!(reader.read_frame().unwrap().unwrap(), b"{\"b\":2");
+        assert_eq!(reader.read_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn ends_cleanly_on_whitespace_before_eof() {
+        assert_eq!(frames(vec![b"{}\n", b"  \n"]), vec![b"{}".to_vec()]);
+    }
+}
@@ -1,16 +1,26 @@
 use std::{
+    collections::HashMap,
     fs,
-    io::{Read, Write},
+    net::Shutdown,
     os::unix::net::{UnixListener, UnixStream},
     path::Path,
+    sync::{
+        Arc, Mutex as StdMutex,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{SyncSender, TrySendError, sync_channel},
+    },
+    time::Duration,
 };
 
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use tokio::{
-    sync::{broadcast, mpsc},
+    sync::{broadcast, mpsc, oneshot},
     task::spawn_blocking,
 };
 
+use super::framing::{FrameReader, write_frame};
+
 #[derive(Default)]
 pub struct UnixSocket<REQ, RES>
 where
@@ -86,11 +96,12 @@ impl<
                         })
                         .ok()?;
 
-                    if let Message::Request(req) = msg {
+                    if let Message::Request { id, request } = msg {
                         Some(RequestContext {
                             peer,
+                            id,
                             _request_string: s,
-                            request: Some(req),
+                            request: Some(request),
                         })
                     } else {
                         None
@@ -108,85 +119,206 @@ where
     REQ: Serialize + Send + 'static,
     RES: for<'de> Deserialize<'de> + Clone + Send + 'static,
 {
-    req_tx: mpsc::UnboundedSender<REQ>,
-    res_tx: broadcast::Sender<RES>,
+    req_tx: mpsc::UnboundedSender<(u64, REQ)>,
+    next_id: AtomicU64,
+    pending: PendingCalls<RES>,
+    /// Held strongly by the thread reading responses only, so receivers see the channel close
+    /// along with the connection.
+    events_tx: broadcast::WeakSender<RES>,
+    /// Kept to shut the socket down on drop, which unblocks the thread reading responses.
+    stream: UnixStream,
 }
 
+/// Calls awaiting a response, keyed by request id.
+type PendingCalls<RES> = Arc<StdMutex<HashMap<u64, oneshot::Sender<RES>>>>;
+
+/// How long [`Connection::call`] waits for the matching response.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl<REQ: Serialize + Send + 'static, RES: for<'de> Deserialize<'de> + Clone + Send + 'static>
     Connection<REQ, RES>
 {
     fn new(stream: UnixStream) -> Self {
-        let (req_tx, req_rx) = mpsc::unbounded_channel::<REQ>();
-        let (res_tx, _res_rx) = broadcast::channel(8);
+        let (req_tx, req_rx) = mpsc::unbounded_channel::<(u64, REQ)>();
+        let pending: PendingCalls<RES> = Default::default();
+        let (events_tx, _events_rx) = broadcast::channel(32);
 
         let tx_stream = stream
             .try_clone()
             .expect("Failed to clone UnixStream for transmitting end");
+        let rx_stream = stream
+            .try_clone()
+            .expect("Failed to clone UnixStream for receiving end");
         let _request_handler = tokio::spawn(async move {
             let mut req_rx = req_rx;
             let mut stream = tx_stream;
 
-            loop {
-                if let Some(v) = req_rx.recv().await {
-                    let _ = stream
-                        .write_all(&serde_json::to_vec(&Message::<REQ, ()>::Request(v)).unwrap());
+            while let Some((id, request)) = req_rx.recv().await {
+                let data =
+                    serde_json::to_vec(&Message::<REQ, ()>::Request { id, request }).unwrap();
+                if let Err(err) = write_frame(&mut stream, &data) {
+                    eprintln!("Failed to send request: {err}");
                 }
             }
         });
 
-        let res_tx2 = res_tx.clone();
+        let pending2 = pending.clone();
+        let weak_events_tx = events_tx.downgrade();
 
         let _response_handler = spawn_blocking(move || {
-            let res_tx = res_tx2;
-            let mut stream = stream;
-
-            loop {
-                let mut buf = vec![0; 1024];
-                if let Ok(n_bytes) = stream.read(&mut buf) {
-                    let s = String::from_utf8_lossy(&buf[..n_bytes]).trim().to_string();
-
-                    if let Ok(Message::Response(res)) = serde_json::from_str::<Message<(), RES>>(&s)
-                    {
-                        match res_tx.send(res) {
-                            Ok(_) => {}
-                            Err(_) => {
+            let pending = pending2;
+
+            for frame in FrameReader::new(rx_stream) {
+                let Ok(frame) = frame else {
+                    eprintln!("Error reading socket input. Closing connection...");
+                    break;
+                };
+
+                match serde_json::from_slice::<Message<(), RES>>(&frame) {
+                    Ok(Message::Response { id, response }) => {
+                        let caller = pending.lock().unwrap().remove(&id);
+                        match caller.map(|tx| tx.send(response)) {
+                            Some(Ok(_)) => {}
+                            _ => {
                                 eprintln!("Received response from peer which we didn't handle");
                             }
                         };
                     }
+                    Ok(Message::Push { message }) => {
+                        // Nobody listening for unsolicited messages is not an error.
+                        let _ = events_tx.send(message);
+                    }
+                    _ => {}
                 }
             }
+
+            // Dropping the senders makes every outstanding call fail instead of waiting for a
+            // response that can never arrive.
+            pending.lock().unwrap().clear();
         });
 
-        Self { req_tx, res_tx }
+        Self {
+            req_tx,
+            next_id: AtomicU64::new(0),
+            pending,
+            events_tx: weak_events_tx,
+            stream,
+        }
+    }
+
+    /// Receive every unsolicited message the peer pushes from now on.
+    ///
+    /// Any number of receivers can be held at once, each one sees every message. Receivers are
+    /// closed once the connection is.
+    pub fn subscribe(&self) -> broadcast::Receiver<RES> {
+        match self.events_tx.upgrade() {
+            Some(events_tx) => events_tx.subscribe(),
+            None => broadcast::channel(1).1,
+        }
     }
 
-    pub fn send_request(&mut self, req: REQ) -> Result<(), mpsc::error::SendError<REQ>> {
-        self.req_tx.send(req)
+    /// Send a request without waiting for its response. Returns the id the request was sent with.
+    pub fn send_request(&self, req: REQ) -> Result<u64, mpsc::error::SendError<REQ>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.req_tx
+            .send((id, req))
+            .map_err(|mpsc::error::SendError((_, req))| mpsc::error::SendError(req))?;
+        Ok(id)
     }
 
-    /// Receive a single response
-    pub async fn receive_response(&mut self) -> Result<RES, broadcast::error::RecvError> {
-        self.res_tx.subscribe().recv().await
+    /// Send a request and resolve with its matching response, giving up after [`DEFAULT_CALL_TIMEOUT`].
+    pub async fn call(&self, req: REQ) -> anyhow::Result<RES> {
+        self.call_with_timeout(req, DEFAULT_CALL_TIMEOUT).await
     }
 
-    pub async fn take_response(&mut self, cmp: impl Fn(&RES) -> bool + Send) -> RES {
-        let mut rx = self.res_tx.subscribe();
+    pub async fn call_with_timeout(&self, req: REQ, timeout: Duration) -> anyhow::Result<RES> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+
+        // Register before sending so a fast reply can never arrive ahead of its caller.
+        self.pending.lock().unwrap().insert(id, tx);
 
-        loop {
-            let res = rx.recv().await.unwrap();
+        if self.req_tx.send((id, req)).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(anyhow!("Connection is closed"));
+        }
 
-            if cmp(&res) {
-                return res;
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(_)) => Err(anyhow!("Connection closed before a response was received")),
+            Err(_) => {
+                // Forget the call so a late response cannot be mistaken for another one.
+                self.pending.lock().unwrap().remove(&id);
+                Err(anyhow!("Timed out waiting for a response"))
             }
         }
     }
 }
 
+impl<REQ, RES> Drop for Connection<REQ, RES>
+where
+    REQ: Serialize + Send + 'static,
+    RES: for<'de> Deserialize<'de> + Clone + Send + 'static,
+{
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
 /// The Listener will receive messages from all connected clients and join them into a single channel.
 pub struct Listener {
     //listener: UnixListener,
-    rx: mpsc::UnboundedReceiver<(UnixStream, String)>,
+    rx: mpsc::UnboundedReceiver<(Peer, String)>,
+}
+
+/// Frames queued for a client before it counts as stuck and is disconnected.
+const PEER_BACKLOG: usize = 64;
+
+/// Writing half of a client connection. Replies and pushed messages are written in order by a
+/// thread of its own, so a client which stops reading never blocks the caller.
+#[derive(Clone)]
+struct Peer {
+    tx: SyncSender<Vec<u8>>,
+    /// Shut down once the client falls behind, which also ends the thread reading from it.
+    stream: Arc<UnixStream>,
+}
+
+impl Peer {
+    fn new(stream: UnixStream) -> Self {
+        let (tx, rx) = sync_channel::<Vec<u8>>(PEER_BACKLOG);
+        let mut writer = stream
+            .try_clone()
+            .expect("Failed to clone socket handle for replying");
+
+        spawn_blocking(move || {
+            for frame in rx {
+                if write_frame(&mut writer, &frame).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            tx,
+            stream: Arc::new(stream),
+        }
+    }
+
+    fn send<REQ: Serialize, RES: Serialize>(
+        &self,
+        message: &Message<REQ, RES>,
+    ) -> anyhow::Result<()> {
+        let data = serde_json::to_vec(message)?;
+
+        match self.tx.try_send(data) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let _ = self.stream.shutdown(Shutdown::Both);
+                Err(anyhow!("Client fell behind and was disconnected"))
+            }
+            Err(TrySendError::Disconnected(_)) => Err(anyhow!("Client disconnected")),
+        }
+    }
 }
 
 impl Listener {
@@ -204,48 +336,46 @@ impl Listener {
     }
 }
 
-fn join_messages(mut of: UnixStream, to: mpsc::UnboundedSender<(UnixStream, String)>) {
-    loop {
-        // Compiler seems to think this is unused
-        #[allow(unused_assignments)]
-        let mut string_data = String::new();
-
-        let mut buf = vec![0; 1024];
-        match of.read(&mut buf) {
-            Ok(n_bytes) => {
-                let data = String::from_utf8_lossy(&buf[..n_bytes]).trim().to_string();
-
-                if data.is_empty() {
-                    eprintln!("Received empty message. Closing connection...");
-                    break;
-                }
+fn join_messages(of: UnixStream, to: mpsc::UnboundedSender<(Peer, String)>) {
+    let peer = Peer::new(
+        of.try_clone()
+            .expect("Failed to clone socket handle for replying"),
+    );
 
-                string_data = data;
-            }
+    for frame in FrameReader::new(of) {
+        let string_data = match frame {
+            Ok(data) => String::from_utf8_lossy(&data).trim().to_string(),
             Err(_) => {
                 eprintln!("Error reading socket input. Closing connection...");
                 break;
             }
-        }
+        };
 
         // println!("Joining message '{string_data}' to Listener");
-        let _ = to.send((
-            of.try_clone()
-                .expect("Failed to clone socket handle for replying"),
-            string_data,
-        ));
+        let _ = to.send((peer.clone(), string_data));
     }
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 enum Message<REQ, RES> {
-    Request(REQ),
-    Response(RES),
+    Request {
+        id: u64,
+        request: REQ,
+    },
+    Response {
+        id: u64,
+        response: RES,
+    },
+    /// Sent by the server on its own accord, not in reply to a specific request.
+    Push {
+        message: RES,
+    },
 }
 
 pub struct RequestContext<T: for<'de> Deserialize<'de>> {
-    peer: UnixStream,
+    peer: Peer,
+    id: u64,
     _request_string: String,
     request: Option<T>,
 }
@@ -255,9 +385,69 @@ impl<T: for<'de> Deserialize<'de>> RequestContext<T> {
         self.request.take().unwrap()
     }
 
-    pub fn respond<R: Serialize>(mut self, res: R) -> anyhow::Result<()> {
-        Ok(self
-            .peer
-            .write_all(&serde_json::to_vec(&Message::<(), R>::Response(res))?)?)
+    /// Id of the request, echoed back in the response so the client can match the two.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn respond<R: Serialize>(self, res: R) -> anyhow::Result<()> {
+        self.peer.send(&Message::<(), R>::Response {
+            id: self.id,
+            response: res,
+        })
+    }
+
+    /// Respond with `ack` and keep the connection around for pushing messages to the client.
+    pub fn subscribe<R: Serialize>(self, ack: R) -> anyhow::Result<Subscriber> {
+        let peer = self.peer.clone();
+        self.respond(ack)?;
+        Ok(Subscriber { peer })
+    }
+}
+
+/// Server side handle for pushing unsolicited messages to a single client.
+pub struct Subscriber {
+    peer: Peer,
+}
+
+impl Subscriber {
+    /// Queues `message` without waiting for it to be written. Fails once the client has
+    /// disconnected, or fell so far behind that it was disconnected.
+    pub fn push<R: Serialize>(&self, message: R) -> anyhow::Result<()> {
+        self.peer.send(&Message::<(), R>::Push { message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn disconnects_a_subscriber_which_stops_reading() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let subscriber = Subscriber {
+            peer: Peer::new(server),
+        };
+
+        // Far more than the socket buffer and the backlog hold together.
+        let message = "x".repeat(64 * 1024);
+        let pushed = (0..1000)
+            .take_while(|_| subscriber.push(&message).is_ok())
+            .count();
+        assert!(pushed < 1000, "push kept succeeding for a stuck client");
+        assert!(subscriber.push(&message).is_err());
+
+        // The client sees the connection end instead of hanging.
+        let read_all = spawn_blocking(move || {
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).map(|_| ())
+        });
+        tokio::time::timeout(Duration::from_secs(5), read_all)
+            .await
+            .expect("Connection should be closed")
+            .unwrap()
+            .unwrap();
     }
 }
@@ -0,0 +1,625 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::anyhow;
+use clap::{Args, Parser, Subcommand};
+use tokio::sync::broadcast;
+
+use swww_itch_shared::{
+    config::{Config, WallpaperSource},
+    duration,
+    message::{OutputMode, Position, Request, Response, RotationMode, WallpaperMeta},
+    sun::SolarPhase,
+    swww_ffi::{ResizeMode, TransitionOptions},
+    unix_socket::{self, Connection},
+};
+
+/// Send a request and take the result out of the response it is answered with, which has to be
+/// the given variant.
+macro_rules! call {
+    ($conn:expr, $request:expr, $response:path) => {
+        match $conn.call($request).await? {
+            $response(result) => result.map_err(anyhow::Error::from),
+            _ => Err(anyhow::anyhow!("Unexpected response from daemon")),
+        }
+    };
+}
+
+mod output;
+mod waybar;
+
+use output::Output;
+
+/// Control a running swww-itchd from the command line.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Print machine readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Path to the daemon socket. Defaults to the one set in config.toml
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Switch to a queued background
+    Switch {
+        bg: String,
+        /// Only show it on this output, e.g. "DP-1"
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Apply the upcoming background now
+    Next,
+    /// Go back to the previously shown background
+    Prev,
+    /// Show the background from before the current one in the history again, going further
+    /// back each time
+    Back,
+    /// Freeze the rotation timer
+    Pause,
+    /// Continue a paused rotation timer
+    Resume,
+    /// Print the rotation interval, or set it when given a duration like "15m" or "2h"
+    Interval {
+        #[arg(value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Print the rotation mode, or set it to sequential, shuffle, random or reverse
+    Mode {
+        #[arg(value_parser = parse_mode)]
+        mode: Option<RotationMode>,
+    },
+    /// Print the queued backgrounds in order
+    Queue,
+    /// Print the outputs swww draws on and the background each one shows, or set whether they
+    /// show the same, independent or spanned backgrounds
+    Outputs {
+        #[arg(value_parser = parse_output_mode)]
+        mode: Option<OutputMode>,
+    },
+    /// Move a background before or after another one in the queue
+    Move {
+        bg: String,
+        #[arg(value_parser = parse_position)]
+        position: Position,
+        target: String,
+    },
+    /// List playlists, or create, delete, rename or switch between them
+    Playlist {
+        #[command(subcommand)]
+        action: Option<PlaylistCommand>,
+    },
+    /// Print the transition of a background or the default one, change it, or try one out
+    Transition {
+        #[command(subcommand)]
+        action: Option<TransitionCommand>,
+    },
+    /// Print how long a background stays up, its weight, pin and phase, or change them
+    Wallpaper {
+        #[command(subcommand)]
+        action: WallpaperCommand,
+    },
+    /// Print the format and dimensions of a queued background
+    Info { bg: String },
+    /// Print the files which were left out of the queue and why
+    Rejected,
+    /// Print the backgrounds which failed to show and whether they are skipped
+    Failures,
+    /// Print the directories backgrounds are queued from, or change them
+    Sources {
+        #[command(subcommand)]
+        action: Option<SourcesCommand>,
+    },
+    /// Print what the daemon is showing, when it switches next and whether the backend works
+    Status,
+    /// Print the backgrounds shown lately, newest first, with when and why they were shown
+    History,
+    /// Print daemon events as they happen until interrupted
+    Events,
+    /// Print a waybar custom module line whenever the status changes, until interrupted
+    Waybar,
+}
+
+#[derive(Subcommand)]
+enum PlaylistCommand {
+    /// Create a playlist with the given backgrounds, or with every background if none are given
+    Create { name: String, bgs: Vec<String> },
+    /// Delete a playlist other than the active one
+    Delete { name: String },
+    /// Rename a playlist
+    Rename { from: String, to: String },
+    /// Play a playlist with its own order, interval and rotation mode
+    Activate { name: String },
+    /// Play a playlist whenever it is day, twilight or night outside, or only when activated
+    /// without a phase. Needs a [location] in config.toml
+    Phase {
+        name: String,
+        #[arg(value_parser = parse_phase)]
+        phase: Option<SolarPhase>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TransitionCommand {
+    /// Print the transition a background is shown with, or the default one
+    Show { bg: Option<String> },
+    /// Show a background with its own transition, starting from the one it has now
+    Set {
+        bg: String,
+        #[command(flatten)]
+        options: TransitionArgs,
+    },
+    /// Show a background with the default transition again
+    Reset { bg: String },
+    /// Show a background with a transition right away, leaving the queue alone
+    Preview {
+        bg: String,
+        #[command(flatten)]
+        options: TransitionArgs,
+    },
+}
+
+/// Transition options, each falling back to the transition the background has now.
+#[derive(Args)]
+struct TransitionArgs {
+    /// One of none, simple, fade, left, right, top, bottom, wipe, wave, grow, center, any,
+    /// outer or random
+    #[arg(long = "type")]
+    transition_type: Option<String>,
+    #[arg(long)]
+    step: Option<u8>,
+    /// Length of the transition in seconds
+    #[arg(long)]
+    duration: Option<f32>,
+    #[arg(long)]
+    angle: Option<f32>,
+    /// e.g. "center", "top-left" or "0.5,0.8"
+    #[arg(long)]
+    position: Option<String>,
+    /// e.g. ".54,0,.34,.99"
+    #[arg(long)]
+    bezier: Option<String>,
+    /// e.g. "20,20"
+    #[arg(long)]
+    wave: Option<String>,
+    #[arg(long)]
+    fps: Option<u32>,
+    /// crop, fit or no
+    #[arg(long, value_parser = parse_resize)]
+    resize: Option<ResizeMode>,
+    /// e.g. "000000"
+    #[arg(long)]
+    fill_color: Option<String>,
+}
+
+impl TransitionArgs {
+    fn apply_to(self, mut transition: TransitionOptions) -> TransitionOptions {
+        if let Some(transition_type) = self.transition_type {
+            transition.transition_type = transition_type;
+        }
+        if let Some(fps) = self.fps {
+            transition.fps = fps;
+        }
+        transition.step = self.step.or(transition.step);
+        transition.duration = self.duration.or(transition.duration);
+        transition.angle = self.angle.or(transition.angle);
+        transition.position = self.position.or(transition.position);
+        transition.bezier = self.bezier.or(transition.bezier);
+        transition.wave = self.wave.or(transition.wave);
+        transition.resize = self.resize.or(transition.resize);
+        transition.fill_color = self.fill_color.or(transition.fill_color);
+        transition
+    }
+}
+
+#[derive(Subcommand)]
+enum WallpaperCommand {
+    /// Print the settings of a background
+    Show { bg: String },
+    /// Change the settings of a background, keeping the ones which are not given
+    Set {
+        bg: String,
+        /// How long the background stays up in place of the interval, e.g. "15m"
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// How often random mode picks the background, and how early shuffle mode does,
+        /// relative to the default of 1
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        weight: Option<u32>,
+        /// Stay on the background once it is shown, until switching by hand
+        #[arg(long, conflicts_with = "unpin")]
+        pin: bool,
+        /// Rotate away from the background again
+        #[arg(long)]
+        unpin: bool,
+        /// Only show the background while it is day, twilight or night outside. Needs a
+        /// [location] in config.toml
+        #[arg(long, value_parser = parse_phase, conflicts_with = "any_phase")]
+        phase: Option<SolarPhase>,
+        /// Show the background whatever the phase
+        #[arg(long)]
+        any_phase: bool,
+    },
+    /// Give a background the default settings again
+    Reset { bg: String },
+}
+
+#[derive(Subcommand)]
+enum SourcesCommand {
+    /// Queue backgrounds from another directory
+    Add {
+        path: String,
+        /// Descend into subdirectories
+        #[arg(short, long)]
+        recursive: bool,
+        /// How many levels of subdirectories to descend into
+        #[arg(long, requires = "recursive")]
+        max_depth: Option<usize>,
+        /// Only queue backgrounds whose path below the directory matches this glob
+        #[arg(long)]
+        include: Vec<String>,
+        /// Skip backgrounds whose path below the directory matches this glob
+        #[arg(long)]
+        exclude: Vec<String>,
+    },
+    /// Stop queueing backgrounds from a directory
+    Remove { path: String },
+}
+
+fn parse_duration(s: &str) -> Result<Duration, &'static str> {
+    duration::parse_duration(s)
+}
+
+fn parse_mode(s: &str) -> Result<RotationMode, &'static str> {
+    RotationMode::try_from(s)
+}
+
+fn parse_output_mode(s: &str) -> Result<OutputMode, &'static str> {
+    OutputMode::try_from(s)
+}
+
+fn parse_phase(s: &str) -> Result<SolarPhase, &'static str> {
+    SolarPhase::try_from(s)
+}
+
+fn parse_resize(s: &str) -> Result<ResizeMode, &'static str> {
+    ResizeMode::try_from(s)
+}
+
+fn parse_position(s: &str) -> Result<Position, &'static str> {
+    Position::try_from(s)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let out = Output::new(cli.json);
+
+    let socket_path = match cli.socket {
+        Some(path) => path,
+        None => Config::load_default()?.socket_path()?,
+    };
+
+    // Status bars start before the daemon, so this one waits for it instead of failing.
+    if let Command::Waybar = cli.command {
+        return waybar::run(&socket_path).await;
+    }
+
+    let socket = unix_socket::connect::<Request, Response, _>(&socket_path);
+    let conn = socket.connection.as_ref().ok_or(anyhow!(
+        "Could not connect to swww-itchd at {}",
+        socket_path.display()
+    ))?;
+
+    match cli.command {
+        Command::Switch { bg, output } => {
+            call!(
+                conn,
+                Request::SwitchToBackground((resolve(bg), output)),
+                Response::SwitchToBackground
+            )?;
+            out.ok();
+        }
+        Command::Next => {
+            call!(conn, Request::Next, Response::Next)?;
+            out.ok();
+        }
+        Command::Prev => {
+            call!(conn, Request::Previous, Response::Previous)?;
+            out.ok();
+        }
+        Command::Back => {
+            call!(conn, Request::Back, Response::Back)?;
+            out.ok();
+        }
+        Command::Pause => {
+            call!(conn, Request::Pause, Response::Pause)?;
+            out.ok();
+        }
+        Command::Resume => {
+            call!(conn, Request::Resume, Response::Resume)?;
+            out.ok();
+        }
+        Command::Interval {
+            duration: Some(duration),
+        } => {
+            call!(
+                conn,
+                Request::SetInterval(duration.as_secs()),
+                Response::SetInterval
+            )?;
+            out.ok();
+        }
+        Command::Interval { duration: None } => {
+            let interval = call!(conn, Request::GetInterval, Response::GetInterval)?;
+            out.interval(Duration::from_secs(interval));
+        }
+        Command::Mode { mode: Some(mode) } => {
+            call!(conn, Request::SetMode(mode), Response::SetMode)?;
+            out.ok();
+        }
+        Command::Mode { mode: None } => {
+            out.mode(call!(conn, Request::GetMode, Response::GetMode)?);
+        }
+        Command::Queue => {
+            out.queue(&call!(conn, Request::GetQueue, Response::GetQueue)?);
+        }
+        Command::Outputs { mode: Some(mode) } => {
+            call!(conn, Request::SetOutputMode(mode), Response::SetOutputMode)?;
+            out.ok();
+        }
+        Command::Outputs { mode: None } => {
+            out.outputs(&call!(conn, Request::GetOutputs, Response::GetOutputs)?);
+        }
+        Command::Move {
+            bg,
+            position,
+            target,
+        } => {
+            let (from, to) = call!(
+                conn,
+                Request::RearrangeBackground((resolve(bg), position, resolve(target))),
+                Response::RearrangeBackground
+            )?;
+            out.moved(from, to);
+        }
+        Command::Playlist { action: None } => {
+            let playlists = call!(conn, Request::ListPlaylists, Response::ListPlaylists)?;
+            out.playlists(&playlists);
+        }
+        Command::Playlist {
+            action: Some(action),
+        } => {
+            match action {
+                PlaylistCommand::Create { name, bgs } => {
+                    let bgs = match bgs.is_empty() {
+                        true => None,
+                        false => Some(bgs.into_iter().map(resolve).collect()),
+                    };
+                    call!(
+                        conn,
+                        Request::CreatePlaylist((name, bgs)),
+                        Response::CreatePlaylist
+                    )?
+                }
+                PlaylistCommand::Delete { name } => call!(
+                    conn,
+                    Request::DeletePlaylist(name),
+                    Response::DeletePlaylist
+                )?,
+                PlaylistCommand::Rename { from, to } => call!(
+                    conn,
+                    Request::RenamePlaylist((from, to)),
+                    Response::RenamePlaylist
+                )?,
+                PlaylistCommand::Activate { name } => call!(
+                    conn,
+                    Request::ActivatePlaylist(name),
+                    Response::ActivatePlaylist
+                )?,
+                PlaylistCommand::Phase { name, phase } => call!(
+                    conn,
+                    Request::SetPlaylistPhase((name, phase)),
+                    Response::SetPlaylistPhase
+                )?,
+            }
+            out.ok();
+        }
+        Command::Transition { action: None } => out.transition(&get_transition(conn, None).await?),
+        Command::Transition {
+            action: Some(TransitionCommand::Show { bg }),
+        } => out.transition(&get_transition(conn, bg.map(resolve)).await?),
+        Command::Transition {
+            action: Some(TransitionCommand::Set { bg, options }),
+        } => {
+            let bg = resolve(bg);
+            let transition = options.apply_to(get_transition(conn, Some(bg.clone())).await?);
+
+            call!(
+                conn,
+                Request::SetTransition((bg, Some(transition))),
+                Response::SetTransition
+            )?;
+            out.ok();
+        }
+        Command::Transition {
+            action: Some(TransitionCommand::Reset { bg }),
+        } => {
+            call!(
+                conn,
+                Request::SetTransition((resolve(bg), None)),
+                Response::SetTransition
+            )?;
+            out.ok();
+        }
+        Command::Transition {
+            action: Some(TransitionCommand::Preview { bg, options }),
+        } => {
+            let bg = resolve(bg);
+            let transition = options.apply_to(get_transition(conn, Some(bg.clone())).await?);
+
+            call!(
+                conn,
+                Request::PreviewTransition((bg, transition)),
+                Response::PreviewTransition
+            )?;
+            out.ok();
+        }
+        Command::Wallpaper {
+            action: WallpaperCommand::Show { bg },
+        } => out.wallpaper_meta(&get_wallpaper_meta(conn, resolve(bg)).await?),
+        Command::Wallpaper {
+            action:
+                WallpaperCommand::Set {
+                    bg,
+                    duration,
+                    weight,
+                    pin,
+                    unpin,
+                    phase,
+                    any_phase,
+                },
+        } => {
+            let bg = resolve(bg);
+            let mut meta = get_wallpaper_meta(conn, bg.clone()).await?;
+            meta.duration = duration
+                .map(|duration| duration.as_secs())
+                .or(meta.duration);
+            meta.weight = weight.or(meta.weight);
+            meta.pinned = (meta.pinned || pin) && !unpin;
+            meta.phase = phase.or(meta.phase).filter(|_| !any_phase);
+
+            call!(
+                conn,
+                Request::SetWallpaperMeta((bg, meta)),
+                Response::SetWallpaperMeta
+            )?;
+            out.ok();
+        }
+        Command::Wallpaper {
+            action: WallpaperCommand::Reset { bg },
+        } => {
+            call!(
+                conn,
+                Request::SetWallpaperMeta((resolve(bg), WallpaperMeta::default())),
+                Response::SetWallpaperMeta
+            )?;
+            out.ok();
+        }
+        Command::Info { bg } => {
+            let info = call!(
+                conn,
+                Request::GetImageInfo(resolve(bg)),
+                Response::GetImageInfo
+            )?;
+            out.image_info(&info);
+        }
+        Command::Rejected => {
+            out.rejected(&call!(conn, Request::GetRejected, Response::GetRejected)?);
+        }
+        Command::Failures => {
+            out.failures(&call!(conn, Request::GetFailures, Response::GetFailures)?);
+        }
+        Command::Sources { action: None } => out.sources(&get_sources(conn).await?),
+        Command::Sources {
+            action: Some(action),
+        } => {
+            let mut sources = get_sources(conn).await?;
+
+            match action {
+                SourcesCommand::Add {
+                    path,
+                    recursive,
+                    max_depth,
+                    include,
+                    exclude,
+                } => {
+                    let path = resolve(path);
+                    sources.retain(|source| source.path != path);
+                    sources.push(WallpaperSource {
+                        path,
+                        recursive,
+                        max_depth,
+                        include,
+                        exclude,
+                    });
+                }
+                SourcesCommand::Remove { path } => {
+                    let len = sources.len();
+                    let resolved = resolve(path.clone());
+                    sources.retain(|source| source.path != path && source.path != resolved);
+                    if sources.len() == len {
+                        return Err(anyhow!("{path} is not a source"));
+                    }
+                }
+            }
+
+            call!(conn, Request::SetSources(sources), Response::SetSources)?;
+            out.ok();
+        }
+        Command::Status => {
+            out.status(&call!(conn, Request::GetStatus, Response::GetStatus)?);
+        }
+        Command::History => {
+            out.history(&call!(conn, Request::GetHistory, Response::GetHistory)?);
+        }
+        Command::Events => stream_events(conn, &out).await?,
+        Command::Waybar => unreachable!("Handled before connecting"),
+    }
+
+    Ok(())
+}
+
+/// Backgrounds are queued by their canonical path. Resolve paths which exist on disk so that
+/// `itchctl switch ./foo.png` works, anything else is passed through untouched.
+fn resolve(bg: String) -> String {
+    std::fs::canonicalize(&bg)
+        .ok()
+        .and_then(|p| p.to_str().map(str::to_string))
+        .unwrap_or(bg)
+}
+
+async fn get_sources(conn: &Connection<Request, Response>) -> anyhow::Result<Vec<WallpaperSource>> {
+    call!(conn, Request::GetSources, Response::GetSources)
+}
+
+async fn get_transition(
+    conn: &Connection<Request, Response>,
+    bg: Option<String>,
+) -> anyhow::Result<TransitionOptions> {
+    call!(conn, Request::GetTransition(bg), Response::GetTransition)
+}
+
+async fn get_wallpaper_meta(
+    conn: &Connection<Request, Response>,
+    bg: String,
+) -> anyhow::Result<WallpaperMeta> {
+    call!(
+        conn,
+        Request::GetWallpaperMeta(bg),
+        Response::GetWallpaperMeta
+    )
+}
+
+async fn stream_events(conn: &Connection<Request, Response>, out: &Output) -> anyhow::Result<()> {
+    let mut events = conn.subscribe();
+
+    call!(conn, Request::Subscribe, Response::Subscribe)?;
+
+    loop {
+        match events.recv().await {
+            Ok(Response::Event(event)) => out.event(&event),
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => {
+                return Err(anyhow!("Daemon closed the connection"));
+            }
+        }
+    }
+}
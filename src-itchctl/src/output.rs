@@ -0,0 +1,592 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::json;
+
+use swww_itch_shared::{
+    config::WallpaperSource,
+    duration::format_duration,
+    message::{
+        Event, FailedWallpaper, HistoryEntry, ImageInfo, OutputInfo, PlaylistInfo, RejectedFile,
+        RotationMode, Status, WallpaperMeta,
+    },
+    swww_ffi::TransitionOptions,
+};
+
+/// `println!` through [`Output::print`].
+macro_rules! out {
+    ($out:expr, $($arg:tt)*) => {
+        $out.print(format!($($arg)*))
+    };
+}
+
+/// Prints results either as plain text or, with `--json`, one JSON value per line.
+pub struct Output {
+    json: bool,
+    /// Lines printed so far, kept instead of printed in tests.
+    #[cfg(test)]
+    printed: std::cell::RefCell<Vec<String>>,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Self {
+            json,
+            #[cfg(test)]
+            printed: Default::default(),
+        }
+    }
+
+    fn print(&self, line: String) {
+        #[cfg(test)]
+        self.printed.borrow_mut().push(line);
+        #[cfg(not(test))]
+        println!("{line}");
+    }
+
+    fn print_json<T: Serialize>(&self, value: &T) {
+        self.print(serde_json::to_string(value).expect("Output should always serialize"));
+    }
+
+    pub fn ok(&self) {
+        if self.json {
+            self.print_json(&json!({ "ok": true }));
+        }
+    }
+
+    pub fn queue(&self, queue: &[String]) {
+        if self.json {
+            self.print_json(&queue);
+        } else {
+            for bg in queue {
+                out!(self, "{bg}");
+            }
+        }
+    }
+
+    pub fn interval(&self, interval: Duration) {
+        if self.json {
+            self.print_json(&json!({ "interval": interval.as_secs() }));
+        } else {
+            out!(self, "{}", format_duration(interval));
+        }
+    }
+
+    pub fn mode(&self, mode: RotationMode) {
+        if self.json {
+            self.print_json(&json!({ "mode": mode }));
+        } else {
+            out!(self, "{mode}");
+        }
+    }
+
+    pub fn outputs(&self, outputs: &[OutputInfo]) {
+        if self.json {
+            self.print_json(&outputs);
+            return;
+        }
+
+        for output in outputs {
+            match &output.wallpaper {
+                Some(wallpaper) => out!(self, "{} {wallpaper}", output.name),
+                None => out!(self, "{}", output.name),
+            }
+        }
+    }
+
+    pub fn playlists(&self, playlists: &[PlaylistInfo]) {
+        if self.json {
+            self.print_json(&playlists);
+            return;
+        }
+
+        for playlist in playlists {
+            out!(
+                self,
+                "{} {} ({} backgrounds, {}, every {}{}{})",
+                if playlist.active { "*" } else { " " },
+                playlist.name,
+                playlist.length,
+                playlist.mode,
+                format_duration(Duration::from_secs(playlist.interval)),
+                if playlist.follows_sources {
+                    ", follows sources"
+                } else {
+                    ""
+                },
+                match playlist.phase {
+                    Some(phase) => format!(", at {phase}"),
+                    None => String::new(),
+                },
+            );
+        }
+    }
+
+    pub fn moved(&self, from: usize, to: usize) {
+        if self.json {
+            self.print_json(&json!({ "moveIndex": from, "toIndex": to }));
+        } else {
+            out!(self, "Moved {from} -> {to}");
+        }
+    }
+
+    pub fn transition(&self, transition: &TransitionOptions) {
+        if self.json {
+            self.print_json(transition);
+            return;
+        }
+
+        let mut options = vec![
+            format!("type={}", transition.transition_type),
+            format!("fps={}", transition.fps),
+        ];
+        let optional = [
+            ("step", transition.step.map(|step| step.to_string())),
+            ("duration", transition.duration.map(|secs| secs.to_string())),
+            ("angle", transition.angle.map(|angle| angle.to_string())),
+            ("position", transition.position.clone()),
+            ("bezier", transition.bezier.clone()),
+            ("wave", transition.wave.clone()),
+            ("resize", transition.resize.map(|resize| resize.to_string())),
+            ("fill_color", transition.fill_color.clone()),
+        ];
+        options.extend(
+            optional
+                .into_iter()
+                .filter_map(|(key, value)| value.map(|value| format!("{key}={value}"))),
+        );
+
+        out!(self, "{}", options.join(" "));
+    }
+
+    pub fn wallpaper_meta(&self, meta: &WallpaperMeta) {
+        if self.json {
+            self.print_json(meta);
+            return;
+        }
+
+        let mut settings = vec![];
+        if let Some(duration) = meta.duration {
+            settings.push(format!(
+                "duration={}",
+                format_duration(Duration::from_secs(duration))
+            ));
+        }
+        settings.push(format!("weight={}", meta.weight()));
+        if meta.pinned {
+            settings.push("pinned".to_string());
+        }
+        if let Some(phase) = meta.phase {
+            settings.push(format!("phase={phase}"));
+        }
+
+        out!(self, "{}", settings.join(" "));
+    }
+
+    pub fn image_info(&self, info: &ImageInfo) {
+        if self.json {
+            self.print_json(info);
+        } else {
+            out!(self, "{} {}x{}", info.format, info.width, info.height);
+        }
+    }
+
+    pub fn rejected(&self, rejected: &[RejectedFile]) {
+        if self.json {
+            self.print_json(&rejected);
+        } else {
+            for file in rejected {
+                out!(self, "{}: {}", file.path, file.reason);
+            }
+        }
+    }
+
+    pub fn failures(&self, failures: &[FailedWallpaper]) {
+        if self.json {
+            self.print_json(&failures);
+            return;
+        }
+
+        for failed in failures {
+            let skipped = if failed.skipped { ", skipped" } else { "" };
+            out!(
+                self,
+                "{} ({} failures{skipped}): {}",
+                failed.path,
+                failed.failures,
+                failed.error
+            );
+        }
+    }
+
+    pub fn history(&self, history: &[HistoryEntry]) {
+        if self.json {
+            self.print_json(&history);
+            return;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for entry in history {
+            let ago = Duration::from_secs(now.saturating_sub(entry.shown_at));
+            out!(
+                self,
+                "{} ({}, {} ago)",
+                entry.path,
+                entry.reason,
+                format_duration(ago)
+            );
+        }
+    }
+
+    pub fn sources(&self, sources: &[WallpaperSource]) {
+        if self.json {
+            self.print_json(&sources);
+            return;
+        }
+
+        for source in sources {
+            let mut options = Vec::new();
+            if source.recursive {
+                options.push(match source.max_depth {
+                    Some(depth) => format!("recursive, max depth {depth}"),
+                    None => "recursive".to_string(),
+                });
+            }
+            options.extend(source.include.iter().map(|glob| format!("include {glob}")));
+            options.extend(source.exclude.iter().map(|glob| format!("exclude {glob}")));
+
+            match options.is_empty() {
+                true => out!(self, "{}", source.path),
+                false => out!(self, "{} ({})", source.path, options.join(", ")),
+            }
+        }
+    }
+
+    pub fn status(&self, status: &Status) {
+        if self.json {
+            self.print_json(status);
+            return;
+        }
+
+        match (&status.wallpaper, status.index) {
+            (Some(wallpaper), Some(index)) => out!(self, "wallpaper: {wallpaper} ({index})"),
+            (Some(wallpaper), None) => out!(self, "wallpaper: {wallpaper}"),
+            (None, _) => out!(self, "wallpaper: none yet"),
+        }
+        if let Some(upcoming) = &status.upcoming {
+            out!(self, "upcoming: {upcoming}");
+        }
+        out!(self, "playlist: {}", status.playlist);
+        if let Some(rule) = &status.rule {
+            out!(self, "rule: {rule}");
+        }
+        out!(
+            self,
+            "mode: {} ({} outputs)",
+            status.mode,
+            status.output_mode
+        );
+        out!(
+            self,
+            "interval: {}",
+            format_duration(Duration::from_secs(status.interval))
+        );
+        if status.duration != status.interval {
+            out!(
+                self,
+                "duration: {}",
+                format_duration(Duration::from_secs(status.duration))
+            );
+        }
+        out!(
+            self,
+            "next switch: in {}{}",
+            format_duration(Duration::from_secs(status.next_switch)),
+            if status.paused { " (paused)" } else { "" }
+        );
+        match &status.backend.error {
+            Some(error) => out!(self, "backend: not ready: {error}"),
+            None => out!(self, "backend: ready"),
+        }
+        if status.backend.skipped > 0 {
+            out!(self, "skipped: {} backgrounds", status.backend.skipped);
+        }
+        out!(self, "version: {}", status.version);
+    }
+
+    pub fn event(&self, event: &Event) {
+        if self.json {
+            self.print_json(event);
+            return;
+        }
+
+        match event {
+            Event::WallpaperChanged((path, index, None)) => out!(self, "wallpaper {index} {path}"),
+            Event::WallpaperChanged((path, index, Some(output))) => {
+                out!(self, "wallpaper {index} {path} on {output}")
+            }
+            Event::QueueReordered((from, to)) => out!(self, "reordered {from} -> {to}"),
+            Event::QueueChanged(queue) => out!(self, "queue changed ({} backgrounds)", queue.len()),
+            Event::IntervalChanged(secs) => {
+                out!(
+                    self,
+                    "interval {}",
+                    format_duration(Duration::from_secs(*secs))
+                )
+            }
+            Event::PausedChanged(true) => out!(self, "paused"),
+            Event::PausedChanged(false) => out!(self, "resumed"),
+            Event::ModeChanged(mode) => out!(self, "mode {mode}"),
+            Event::PlaylistsChanged(playlists) => {
+                let active = playlists.iter().find(|playlist| playlist.active);
+                match active {
+                    Some(active) => out!(self, "playlists changed (active: {})", active.name),
+                    None => out!(self, "playlists changed"),
+                }
+            }
+            Event::FileRejected(rejected) => {
+                out!(self, "rejected {}: {}", rejected.path, rejected.reason)
+            }
+            Event::WallpaperFailed(failed) => {
+                out!(
+                    self,
+                    "failed {} ({}x): {}",
+                    failed.path,
+                    failed.failures,
+                    failed.error
+                )
+            }
+            Event::RuleChanged(Some(rule)) => out!(self, "rule {rule}"),
+            Event::RuleChanged(None) => out!(self, "no rule"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use swww_itch_shared::{
+        message::{BackendStatus, OutputMode},
+        sun::SolarPhase,
+    };
+
+    use super::*;
+
+    /// A daemon half way through showing forest.png.
+    pub(crate) fn status() -> Status {
+        Status {
+            version: "0.1.0".to_string(),
+            wallpaper: Some("/bg/forest.png".to_string()),
+            index: Some(1),
+            upcoming: Some("/bg/lake.png".to_string()),
+            playlist: "default".to_string(),
+            rule: None,
+            mode: RotationMode::Sequential,
+            output_mode: OutputMode::Same,
+            interval: 60 * 60,
+            duration: 60 * 60,
+            next_switch: 30 * 60,
+            paused: false,
+            backend: BackendStatus {
+                ready: true,
+                error: None,
+                skipped: 0,
+            },
+        }
+    }
+
+    fn text(print: impl FnOnce(&Output)) -> Vec<String> {
+        let out = Output::new(false);
+        print(&out);
+        out.printed.take()
+    }
+
+    fn json(print: impl FnOnce(&Output)) -> Vec<serde_json::Value> {
+        let out = Output::new(true);
+        print(&out);
+        out.printed
+            .take()
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn prints_the_status_as_text() {
+        assert_eq!(
+            text(|out| out.status(&status())),
+            [
+                "wallpaper: /bg/forest.png (1)",
+                "upcoming: /bg/lake.png",
+                "playlist: default",
+                "mode: sequential (same outputs)",
+                "interval: 1h",
+                "next switch: in 30m",
+                "backend: ready",
+                "version: 0.1.0",
+            ]
+        );
+
+        let status = Status {
+            wallpaper: None,
+            rule: Some("evening".to_string()),
+            duration: 3 * 60 * 60,
+            paused: true,
+            backend: BackendStatus {
+                ready: false,
+                error: Some("swww-daemon is not running".to_string()),
+                skipped: 2,
+            },
+            ..status()
+        };
+        assert_eq!(
+            text(|out| out.status(&status)),
+            [
+                "wallpaper: none yet",
+                "upcoming: /bg/lake.png",
+                "playlist: default",
+                "rule: evening",
+                "mode: sequential (same outputs)",
+                "interval: 1h",
+                "duration: 3h",
+                "next switch: in 30m (paused)",
+                "backend: not ready: swww-daemon is not running",
+                "skipped: 2 backgrounds",
+                "version: 0.1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn prints_the_status_as_one_json_line() {
+        let printed = json(|out| out.status(&status()));
+        assert_eq!(printed.len(), 1);
+        assert_eq!(printed[0]["wallpaper"], "/bg/forest.png");
+        assert_eq!(printed[0]["nextSwitch"], 30 * 60);
+        assert_eq!(printed[0]["backend"]["ready"], true);
+    }
+
+    #[test]
+    fn confirms_success_only_in_json() {
+        assert!(text(Output::ok).is_empty());
+        assert_eq!(json(Output::ok), [serde_json::json!({ "ok": true })]);
+    }
+
+    #[test]
+    fn prints_plain_values_as_json_objects() {
+        let interval = Duration::from_secs(90 * 60);
+        assert_eq!(text(|out| out.interval(interval)), ["1h30m"]);
+        assert_eq!(
+            json(|out| out.interval(interval)),
+            [serde_json::json!({ "interval": 5400 })]
+        );
+        assert_eq!(
+            json(|out| out.mode(RotationMode::Shuffle)),
+            [serde_json::json!({ "mode": "shuffle" })]
+        );
+        assert_eq!(
+            json(|out| out.moved(3, 0)),
+            [serde_json::json!({ "moveIndex": 3, "toIndex": 0 })]
+        );
+    }
+
+    #[test]
+    fn prints_playlists() {
+        let playlists = [
+            PlaylistInfo {
+                name: "default".to_string(),
+                active: true,
+                follows_sources: true,
+                length: 12,
+                interval: 60 * 60,
+                mode: RotationMode::Sequential,
+                phase: None,
+            },
+            PlaylistInfo {
+                name: "dark".to_string(),
+                active: false,
+                follows_sources: false,
+                length: 3,
+                interval: 15 * 60,
+                mode: RotationMode::Shuffle,
+                phase: Some(SolarPhase::Night),
+            },
+        ];
+        assert_eq!(
+            text(|out| out.playlists(&playlists)),
+            [
+                "* default (12 backgrounds, sequential, every 1h, follows sources)",
+                "  dark (3 backgrounds, shuffle, every 15m, at night)",
+            ]
+        );
+
+        let printed = json(|out| out.playlists(&playlists));
+        assert_eq!(printed[0][1]["phase"], "night");
+        assert_eq!(printed[0][1]["followsSources"], false);
+    }
+
+    #[test]
+    fn prints_only_the_wallpaper_settings_which_are_set() {
+        assert_eq!(
+            text(|out| out.wallpaper_meta(&WallpaperMeta::default())),
+            ["weight=1"]
+        );
+
+        let meta = WallpaperMeta {
+            duration: Some(15 * 60),
+            weight: Some(2),
+            pinned: true,
+            phase: Some(SolarPhase::Day),
+        };
+        assert_eq!(
+            text(|out| out.wallpaper_meta(&meta)),
+            ["duration=15m weight=2 pinned phase=day"]
+        );
+    }
+
+    #[test]
+    fn prints_sources_with_their_options() {
+        let sources = [
+            WallpaperSource::new("/bg"),
+            WallpaperSource {
+                path: "/themes".to_string(),
+                recursive: true,
+                max_depth: Some(2),
+                include: vec!["*.png".to_string()],
+                exclude: vec!["old/**".to_string()],
+            },
+        ];
+        assert_eq!(
+            text(|out| out.sources(&sources)),
+            [
+                "/bg",
+                "/themes (recursive, max depth 2, include *.png, exclude old/**)",
+            ]
+        );
+    }
+
+    #[test]
+    fn prints_events() {
+        let events = [
+            Event::WallpaperChanged(("/bg/lake.png".to_string(), 2, None)),
+            Event::WallpaperChanged(("/bg/lake.png".to_string(), 2, Some("DP-1".to_string()))),
+            Event::PausedChanged(true),
+            Event::RuleChanged(None),
+        ];
+        assert_eq!(
+            text(|out| events.iter().for_each(|event| out.event(event))),
+            [
+                "wallpaper 2 /bg/lake.png",
+                "wallpaper 2 /bg/lake.png on DP-1",
+                "paused",
+                "no rule",
+            ]
+        );
+        assert_eq!(
+            json(|out| out.event(&events[2])),
+            [serde_json::json!({ "pausedChanged": true })]
+        );
+    }
+}
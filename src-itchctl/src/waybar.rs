@@ -0,0 +1,242 @@
+use std::{convert::Infallible, path::Path};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use tokio::{
+    sync::broadcast,
+    time::{Duration, Instant, interval, sleep},
+};
+
+use swww_itch_shared::{
+    duration::format_duration,
+    message::{Request, Response, Status},
+    unix_socket::{self, Connection},
+};
+
+/// Delay between attempts to reach a daemon which is not running.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A line in the format of a waybar custom module with `"return-type": "json"`.
+#[derive(Serialize, PartialEq)]
+struct Module {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+    /// Share of the interval left until the next switch.
+    percentage: u64,
+}
+
+impl Module {
+    /// Describe `status`, which was fetched `elapsed` ago.
+    fn new(status: &Status, elapsed: Duration) -> Self {
+        let remaining = match status.paused {
+            true => status.next_switch,
+            false => status.next_switch.saturating_sub(elapsed.as_secs()),
+        };
+
+        let text = status
+            .wallpaper
+            .as_deref()
+            .map(file_name)
+            .unwrap_or("none")
+            .to_string();
+
+        let mut tooltip = vec![
+            status
+                .wallpaper
+                .clone()
+                .unwrap_or("No wallpaper shown yet".to_string()),
+            format!("Playlist: {} ({})", status.playlist, status.mode),
+        ];
+        if let Some(upcoming) = &status.upcoming {
+            let paused = if status.paused { " (paused)" } else { "" };
+            tooltip.push(format!(
+                "Next: {} in {}{paused}",
+                file_name(upcoming),
+                format_duration(Duration::from_secs(remaining))
+            ));
+        }
+        if let Some(error) = &status.backend.error {
+            tooltip.push(format!("Backend not ready: {error}"));
+        }
+
+        let class = if !status.backend.ready {
+            "unavailable"
+        } else if status.paused {
+            "paused"
+        } else {
+            "playing"
+        };
+
+        Self {
+            text,
+            tooltip: tooltip.join("\n"),
+            class,
+            // Exact switch times of a schedule rule can be further apart than the duration.
+            percentage: (remaining * 100)
+                .checked_div(status.duration)
+                .unwrap_or(0)
+                .min(100),
+        }
+    }
+
+    fn disconnected(reason: &str) -> Self {
+        Self {
+            text: "".to_string(),
+            tooltip: format!("swww-itchd is not running: {reason}"),
+            class: "disconnected",
+            percentage: 0,
+        }
+    }
+
+    /// Print the module unless it looks the same as the one printed last.
+    fn print(self, printed: &mut Option<Module>) {
+        if printed.as_ref() == Some(&self) {
+            return;
+        }
+
+        println!(
+            "{}",
+            serde_json::to_string(&self).expect("Module should always serialize")
+        );
+        *printed = Some(self);
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// Print a waybar module line whenever the daemon reports a change, and as the countdown runs
+/// down. Keeps reconnecting while the daemon is not running.
+pub async fn run(socket_path: &Path) -> anyhow::Result<()> {
+    let mut printed = None;
+    loop {
+        let socket = unix_socket::connect::<Request, Response, _>(socket_path);
+        let reason = match socket.connection.as_ref() {
+            Some(conn) => {
+                let Err(err) = follow(conn, &mut printed).await;
+                err
+            }
+            None => anyhow!("Could not connect to {}", socket_path.display()),
+        };
+
+        Module::disconnected(&reason.to_string()).print(&mut printed);
+        sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Follow the daemon until the connection breaks.
+async fn follow(
+    conn: &Connection<Request, Response>,
+    printed: &mut Option<Module>,
+) -> anyhow::Result<Infallible> {
+    let mut events = conn.subscribe();
+
+    call!(conn, Request::Subscribe, Response::Subscribe)?;
+
+    let mut status = get_status(conn).await?;
+    let mut fetched = Instant::now();
+    // The countdown is worked out locally so the daemon is only asked after an event.
+    let mut tick = interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            event = events.recv() => match event {
+                Ok(Response::Event(_)) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    status = get_status(conn).await?;
+                    fetched = Instant::now();
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(anyhow!("Daemon closed the connection"));
+                }
+            },
+        }
+
+        Module::new(&status, fetched.elapsed()).print(printed);
+    }
+}
+
+async fn get_status(conn: &Connection<Request, Response>) -> anyhow::Result<Status> {
+    call!(conn, Request::GetStatus, Response::GetStatus)
+}
+
+#[cfg(test)]
+mod tests {
+    use swww_itch_shared::message::BackendStatus;
+
+    use crate::output::tests::status;
+
+    use super::*;
+
+    #[test]
+    fn describes_a_playing_rotation() {
+        let module = Module::new(&status(), Duration::from_secs(10 * 60));
+        assert_eq!(module.text, "forest.png");
+        assert_eq!(
+            module.tooltip,
+            "/bg/forest.png\nPlaylist: default (sequential)\nNext: lake.png in 20m"
+        );
+        assert_eq!(module.class, "playing");
+        assert_eq!(module.percentage, 33);
+    }
+
+    #[test]
+    fn stops_the_countdown_while_paused() {
+        let status = Status {
+            paused: true,
+            ..status()
+        };
+        let module = Module::new(&status, Duration::from_secs(10 * 60));
+        assert_eq!(module.class, "paused");
+        assert!(module.tooltip.ends_with("Next: lake.png in 30m (paused)"));
+        assert_eq!(module.percentage, 50);
+    }
+
+    #[test]
+    fn tells_when_the_backend_is_down() {
+        let status = Status {
+            wallpaper: None,
+            backend: BackendStatus {
+                ready: false,
+                error: Some("swww-daemon is not running".to_string()),
+                skipped: 0,
+            },
+            ..status()
+        };
+        let module = Module::new(&status, Duration::ZERO);
+        assert_eq!(module.text, "none");
+        assert_eq!(module.class, "unavailable");
+        assert!(module.tooltip.starts_with("No wallpaper shown yet\n"));
+        assert!(
+            module
+                .tooltip
+                .ends_with("\nBackend not ready: swww-daemon is not running")
+        );
+    }
+
+    #[test]
+    fn keeps_the_percentage_between_0_and_100() {
+        let no_duration = Status {
+            duration: 0,
+            ..status()
+        };
+        assert_eq!(Module::new(&no_duration, Duration::ZERO).percentage, 0);
+
+        // Exact switch times of a rule can be further away than the duration.
+        let far = Status {
+            next_switch: 3 * 60 * 60,
+            ..status()
+        };
+        assert_eq!(Module::new(&far, Duration::ZERO).percentage, 100);
+
+        // The countdown ran out since the status was fetched.
+        let late = Module::new(&status(), Duration::from_secs(2 * 60 * 60));
+        assert_eq!(late.percentage, 0);
+    }
+}
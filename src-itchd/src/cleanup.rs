@@ -4,8 +4,11 @@ use tokio::{
     signal::unix::{SignalKind, signal},
 };
 
+use crate::state::StateFile;
+
 pub struct Cleanup {
     pub unix_socket_path: PathBuf,
+    pub state: StateFile,
 }
 
 impl Cleanup {
@@ -27,6 +30,8 @@ impl Cleanup {
                 }
             }
 
+            self.state.flush().await;
+
             std::process::exit(0);
         });
     }
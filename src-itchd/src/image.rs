@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::Path,
+};
+
+use imagesize::{Compression, ImageError, ImageType};
+use swww_itch_shared::message::{ImageInfo, RejectedFile};
+
+/// Outcome of inspecting a file found in a wallpaper source.
+pub enum Probed {
+    /// Canonical path of a usable image.
+    Wallpaper(String, ImageInfo),
+    Rejected(RejectedFile),
+}
+
+impl Probed {
+    pub fn path(&self) -> &str {
+        match self {
+            Probed::Wallpaper(path, _) => path,
+            Probed::Rejected(rejected) => &rejected.path,
+        }
+    }
+}
+
+/// Identify `path` by its magic bytes and read its dimensions from the header, so that
+/// misnamed files are queued and broken ones are not.
+pub fn probe(path: &Path) -> Probed {
+    let canonical = path
+        .canonicalize()
+        .unwrap_or(path.to_path_buf())
+        .to_string_lossy()
+        .to_string();
+
+    match read_header(path) {
+        Ok(info) => Probed::Wallpaper(canonical, info),
+        Err(reason) => Probed::Rejected(RejectedFile {
+            path: canonical,
+            reason,
+        }),
+    }
+}
+
+fn read_header(path: &Path) -> Result<ImageInfo, String> {
+    let file = File::open(path).map_err(|err| format!("Could not read file: {err}"))?;
+    let mut reader = BufReader::new(file);
+
+    let image_type = imagesize::reader_type(&mut reader).map_err(|err| match err {
+        ImageError::IoError(err) if err.kind() == ErrorKind::UnexpectedEof => {
+            "File is too small to be an image".to_string()
+        }
+        err => describe(err),
+    })?;
+    let format = format_name(image_type)
+        .ok_or_else(|| format!("{image_type:?} images are not supported by swww"))?;
+
+    let size = image_type
+        .reader_size(&mut reader)
+        .map_err(|err| match err {
+            ImageError::IoError(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                "Image header is truncated".to_string()
+            }
+            err => describe(err),
+        })?;
+    if size.width == 0 || size.height == 0 {
+        return Err("Image has no pixels".to_string());
+    }
+
+    Ok(ImageInfo {
+        format: format.to_string(),
+        width: size.width,
+        height: size.height,
+    })
+}
+
+/// Formats swww can decode.
+fn format_name(image_type: ImageType) -> Option<&'static str> {
+    match image_type {
+        ImageType::Bmp => Some("bmp"),
+        ImageType::Farbfeld => Some("farbfeld"),
+        ImageType::Gif => Some("gif"),
+        ImageType::Heif(Compression::Av1) => Some("avif"),
+        ImageType::Jpeg => Some("jpeg"),
+        ImageType::Png => Some("png"),
+        ImageType::Pnm => Some("pnm"),
+        ImageType::Tga => Some("tga"),
+        ImageType::Tiff => Some("tiff"),
+        ImageType::Webp => Some("webp"),
+        _ => None,
+    }
+}
+
+fn describe(err: ImageError) -> String {
+    match err {
+        ImageError::NotSupported => "Not a recognised image format".to_string(),
+        ImageError::CorruptedImage => "Image header is corrupted".to_string(),
+        ImageError::IoError(err) => format!("Could not read image header: {err}"),
+    }
+}
+
+/// Image info for queued wallpapers along with the files which were rejected, keyed by canonical
+/// path.
+#[derive(Default, Debug)]
+pub struct Images {
+    info: HashMap<String, ImageInfo>,
+    rejected: BTreeMap<String, String>,
+}
+
+impl Images {
+    /// Record the outcome of a scan, returning the usable wallpapers in the order they were
+    /// found.
+    pub fn extend(&mut self, probed: Vec<Probed>) -> Vec<String> {
+        probed
+            .into_iter()
+            .filter_map(|probed| match probed {
+                Probed::Wallpaper(path, info) => {
+                    self.accept(path.clone(), info);
+                    Some(path)
+                }
+                Probed::Rejected(rejected) => {
+                    self.reject(rejected);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn accept(&mut self, path: String, info: ImageInfo) {
+        self.rejected.remove(&path);
+        self.info.insert(path, info);
+    }
+
+    /// Returns whether the file was not already rejected for the same reason.
+    pub fn reject(&mut self, rejected: RejectedFile) -> bool {
+        self.info.remove(&rejected.path);
+        self.rejected.insert(rejected.path, rejected.reason.clone()) != Some(rejected.reason)
+    }
+
+    pub fn forget(&mut self, path: &str) {
+        self.info.remove(path);
+        self.rejected.remove(path);
+    }
+
+    /// Forget every file below `dir`.
+    pub fn forget_in(&mut self, dir: &Path) {
+        self.info
+            .retain(|path, _| !Path::new(path).starts_with(dir));
+        self.rejected
+            .retain(|path, _| !Path::new(path).starts_with(dir));
+    }
+
+    pub fn clear(&mut self) {
+        self.info.clear();
+        self.rejected.clear();
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ImageInfo> {
+        self.info.get(path)
+    }
+
+    /// Every usable wallpaper, sorted by path.
+    pub fn wallpapers(&self) -> Vec<String> {
+        let mut wallpapers: Vec<String> = self.info.keys().cloned().collect();
+        wallpapers.sort();
+        wallpapers
+    }
+
+    pub fn rejected(&self) -> Vec<RejectedFile> {
+        self.rejected
+            .iter()
+            .map(|(path, reason)| RejectedFile {
+                path: path.clone(),
+                reason: reason.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::wallpaper_queue::wallpapers;
+
+    use super::*;
+
+    /// The start of a 3x2 JPEG, up to the frame header holding its size.
+    const JPEG: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0x02, 0x00, 0x03, 0x03, 0x01, 0x22, 0x00,
+        0x02, 0x11, 0x01, 0x03, 0x11, 0x01,
+    ];
+
+    /// A fresh directory holding a file named `name` with `contents`.
+    fn file(test: &str, name: &str, contents: &[u8]) -> PathBuf {
+        let dir = wallpapers(test, &[]);
+        std::fs::write(dir.join(name), contents).unwrap();
+        dir.join(name)
+    }
+
+    fn png(test: &str) -> Vec<u8> {
+        let dir = wallpapers(&format!("{test}-png"), &["image.png"]);
+        std::fs::read(dir.join("image.png")).unwrap()
+    }
+
+    fn info(path: &Path) -> ImageInfo {
+        match probe(path) {
+            Probed::Wallpaper(_, info) => info,
+            Probed::Rejected(rejected) => panic!("rejected: {}", rejected.reason),
+        }
+    }
+
+    fn reason(path: &Path) -> String {
+        match probe(path) {
+            Probed::Wallpaper(..) => panic!("{} was accepted", path.display()),
+            Probed::Rejected(rejected) => rejected.reason,
+        }
+    }
+
+    #[test]
+    fn reads_an_image_with_an_upper_case_extension() {
+        let path = file("image-upper-case", "photo.JPG", JPEG);
+
+        let info = info(&path);
+        assert_eq!(info.format, "jpeg");
+        assert_eq!((info.width, info.height), (3, 2));
+    }
+
+    #[test]
+    fn reads_an_image_without_an_extension() {
+        let path = file(
+            "image-no-extension",
+            "wallpaper",
+            &png("image-no-extension"),
+        );
+
+        let info = info(&path);
+        assert_eq!(info.format, "png");
+        assert_eq!((info.width, info.height), (1, 1));
+    }
+
+    #[test]
+    fn rejects_a_truncated_image() {
+        let path = file(
+            "image-truncated",
+            "broken.png",
+            &png("image-truncated")[..20],
+        );
+
+        assert_eq!(reason(&path), "Image header is truncated");
+    }
+
+    #[test]
+    fn rejects_a_text_file_named_like_an_image() {
+        let text = b"These are not the pixels you are looking for.\n";
+        let path = file("image-text", "notes.png", text);
+
+        assert_eq!(reason(&path), "Not a recognised image format");
+    }
+
+    #[test]
+    fn rejects_an_empty_file() {
+        let path = file("image-empty", "empty.png", &[]);
+
+        assert_eq!(reason(&path), "File is too small to be an image");
+    }
+}
@@ -1,7 +1,8 @@
 use swww_itch_shared::{
-    message::{Request, Response},
+    message::{Request, Response, SwitchReason},
     unix_socket::UnixSocket,
 };
+use tokio::{sync::broadcast, time::Duration};
 
 use crate::wallpaper_queue::WallpaperQueue;
 
@@ -10,12 +11,15 @@ pub async fn run(mut listener: UnixSocket<Request, ()>, wq: WallpaperQueue) {
     loop {
         if let Some(mut c) = listener.recv().await {
             match c.take_request() {
-                Request::SwitchToBackground(p) => {
-                    println!(r#"Received job: SwitchToBackground("{p}")"#);
+                Request::SwitchToBackground((p, output)) => {
+                    println!(r#"Received job: SwitchToBackground("{p}", {output:?})"#);
 
-                    let success = wq.switch_to_wallpaper(&p).await.is_ok();
+                    let result = wq
+                        .switch_to_wallpaper(&p, output, SwitchReason::Manual)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to switch background: {err}"));
                     let _ = c
-                        .respond(Response::SwitchToBackground(success))
+                        .respond(Response::SwitchToBackground(result))
                         .inspect_err(|err| eprintln!("Failed to send response: {err}"));
                 }
                 Request::RearrangeBackground((bg, before_or_after, target_bg)) => {
@@ -23,17 +27,80 @@ pub async fn run(mut listener: UnixSocket<Request, ()>, wq: WallpaperQueue) {
                         r#"Received job: RearrangeBackground("{bg}", "{before_or_after}", "{target_bg}")"#
                     );
 
-                    let response = wq
+                    let result = wq
                         .rearrange_wallpaper(&bg, &before_or_after, &target_bg)
                         .await
-                        .map(|(move_index, to_index)| (true, move_index, to_index));
+                        .inspect_err(|err| eprintln!("Failed to rearrange: {err}"));
+
+                    let _ = c
+                        .respond(Response::RearrangeBackground(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Next => {
+                    println!("Received job: Next");
+
+                    let _ = c
+                        .respond(Response::Next(wq.next_wallpaper().await))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Previous => {
+                    println!("Received job: Previous");
+
+                    let _ = c
+                        .respond(Response::Previous(wq.previous_wallpaper().await))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Back => {
+                    println!("Received job: Back");
+
+                    let _ = c
+                        .respond(Response::Back(wq.back().await))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Pause => {
+                    println!("Received job: Pause");
+
+                    let _ = c
+                        .respond(Response::Pause(wq.pause().await))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Resume => {
+                    println!("Received job: Resume");
+
+                    let _ = c
+                        .respond(Response::Resume(wq.resume().await))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetInterval(secs) => {
+                    println!("Received job: SetInterval({secs})");
+
+                    let result = wq.set_interval(Duration::from_secs(secs)).await;
+                    let _ = c
+                        .respond(Response::SetInterval(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetInterval => {
+                    println!("Received job: GetInterval");
+
+                    let interval = wq.get_interval().await;
+                    let _ = c
+                        .respond(Response::GetInterval(Ok(interval.as_secs())))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetMode(mode) => {
+                    println!("Received job: SetMode({mode})");
 
+                    let result = wq.set_mode(mode).await;
                     let _ = c
-                        .respond(Response::RearrangeBackground(
-                            response
-                                .inspect_err(|err| eprintln!("Failed to rearrange: {err}"))
-                                .unwrap_or((false, 0, 0)),
-                        ))
+                        .respond(Response::SetMode(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetMode => {
+                    println!("Received job: GetMode");
+
+                    let mode = wq.get_mode().await;
+                    let _ = c
+                        .respond(Response::GetMode(Ok(mode)))
                         .inspect_err(|err| eprintln!("Failed to send response: {err}"));
                 }
                 Request::GetQueue => {
@@ -42,9 +109,229 @@ pub async fn run(mut listener: UnixSocket<Request, ()>, wq: WallpaperQueue) {
                     let queue = wq.get_queue().await;
 
                     let _ = c
-                        .respond(Response::GetQueue(queue))
+                        .respond(Response::GetQueue(Ok(queue)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetOutputs => {
+                    println!("Received job: GetOutputs");
+
+                    let result = wq
+                        .get_outputs()
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to query outputs: {err}"));
+                    let _ = c
+                        .respond(Response::GetOutputs(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetOutputMode(mode) => {
+                    println!("Received job: SetOutputMode({mode})");
+
+                    let result = wq.set_output_mode(mode).await;
+                    let _ = c
+                        .respond(Response::SetOutputMode(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::ListPlaylists => {
+                    println!("Received job: ListPlaylists");
+
+                    let playlists = wq.list_playlists().await;
+                    let _ = c
+                        .respond(Response::ListPlaylists(Ok(playlists)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::CreatePlaylist((name, wallpapers)) => {
+                    println!(r#"Received job: CreatePlaylist("{name}")"#);
+
+                    let result = wq
+                        .create_playlist(name, wallpapers)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to create playlist: {err}"));
+                    let _ = c
+                        .respond(Response::CreatePlaylist(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::DeletePlaylist(name) => {
+                    println!(r#"Received job: DeletePlaylist("{name}")"#);
+
+                    let result = wq
+                        .delete_playlist(&name)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to delete playlist: {err}"));
+                    let _ = c
+                        .respond(Response::DeletePlaylist(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::RenamePlaylist((from, to)) => {
+                    println!(r#"Received job: RenamePlaylist("{from}", "{to}")"#);
+
+                    let result = wq
+                        .rename_playlist(&from, to)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to rename playlist: {err}"));
+                    let _ = c
+                        .respond(Response::RenamePlaylist(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::ActivatePlaylist(name) => {
+                    println!(r#"Received job: ActivatePlaylist("{name}")"#);
+
+                    let result = wq
+                        .activate_playlist(&name)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to activate playlist: {err}"));
+                    let _ = c
+                        .respond(Response::ActivatePlaylist(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetPlaylistPhase((name, phase)) => {
+                    println!(r#"Received job: SetPlaylistPhase("{name}", {phase:?})"#);
+
+                    let result = wq
+                        .set_playlist_phase(&name, phase)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to set playlist phase: {err}"));
+                    let _ = c
+                        .respond(Response::SetPlaylistPhase(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetTransition(bg) => {
+                    println!("Received job: GetTransition({bg:?})");
+
+                    let result = wq.get_transition(bg.as_deref()).await;
+                    let _ = c
+                        .respond(Response::GetTransition(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetTransition((bg, transition)) => {
+                    println!(r#"Received job: SetTransition("{bg}")"#);
+
+                    let result = wq
+                        .set_transition(&bg, transition)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to set transition: {err}"));
+                    let _ = c
+                        .respond(Response::SetTransition(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::PreviewTransition((bg, transition)) => {
+                    println!(r#"Received job: PreviewTransition("{bg}")"#);
+
+                    let result = wq
+                        .preview_transition(&bg, &transition)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to preview transition: {err}"));
+                    let _ = c
+                        .respond(Response::PreviewTransition(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetWallpaperMeta(bg) => {
+                    println!(r#"Received job: GetWallpaperMeta("{bg}")"#);
+
+                    let result = wq.get_wallpaper_meta(&bg).await;
+                    let _ = c
+                        .respond(Response::GetWallpaperMeta(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetWallpaperMeta((bg, meta)) => {
+                    println!(r#"Received job: SetWallpaperMeta("{bg}")"#);
+
+                    let result = wq
+                        .set_wallpaper_meta(&bg, meta)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to set wallpaper settings: {err}"));
+                    let _ = c
+                        .respond(Response::SetWallpaperMeta(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetImageInfo(bg) => {
+                    println!(r#"Received job: GetImageInfo("{bg}")"#);
+
+                    let result = wq.get_image_info(&bg).await;
+                    let _ = c
+                        .respond(Response::GetImageInfo(result))
                         .inspect_err(|err| eprintln!("Failed to send response: {err}"));
                 }
+                Request::GetRejected => {
+                    println!("Received job: GetRejected");
+
+                    let rejected = wq.get_rejected().await;
+                    let _ = c
+                        .respond(Response::GetRejected(Ok(rejected)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetFailures => {
+                    println!("Received job: GetFailures");
+
+                    let failures = wq.get_failures();
+                    let _ = c
+                        .respond(Response::GetFailures(Ok(failures)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetSources => {
+                    println!("Received job: GetSources");
+
+                    let sources = wq.get_sources().await;
+                    let _ = c
+                        .respond(Response::GetSources(Ok(sources)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::SetSources(sources) => {
+                    println!("Received job: SetSources({} sources)", sources.len());
+
+                    let result = wq
+                        .set_sources(sources)
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to set sources: {err}"));
+                    let _ = c
+                        .respond(Response::SetSources(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetStatus => {
+                    println!("Received job: GetStatus");
+
+                    let result = wq.get_status().await;
+                    let _ = c
+                        .respond(Response::GetStatus(result))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::GetHistory => {
+                    println!("Received job: GetHistory");
+
+                    let history = wq.get_history();
+                    let _ = c
+                        .respond(Response::GetHistory(Ok(history)))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"));
+                }
+                Request::Subscribe => {
+                    println!("Received job: Subscribe");
+
+                    let mut events = wq.subscribe();
+
+                    let Ok(subscriber) = c
+                        .subscribe(Response::Subscribe(Ok(())))
+                        .inspect_err(|err| eprintln!("Failed to send response: {err}"))
+                    else {
+                        continue;
+                    };
+
+                    tokio::spawn(async move {
+                        loop {
+                            let event = match events.recv().await {
+                                Ok(event) => event,
+                                Err(broadcast::error::RecvError::Lagged(n)) => {
+                                    eprintln!("Subscriber lagged behind, skipped {n} events");
+                                    continue;
+                                }
+                                Err(broadcast::error::RecvError::Closed) => break,
+                            };
+
+                            if let Err(err) = subscriber.push(Response::Event(event)) {
+                                println!("Dropping subscriber: {err}");
+                                break;
+                            }
+                        }
+                    });
+                }
             }
         }
     }
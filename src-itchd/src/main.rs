@@ -1,40 +1,81 @@
-use anyhow::anyhow;
-
-use swww_itch_shared::unix_socket::{UnixSocketPath, setup_listener};
+use swww_itch_shared::{
+    config::Config, message::SwitchReason, schedule::Schedule, unix_socket::setup_listener,
+};
 
 mod cleanup;
+mod image;
 mod ipc;
+mod sources;
+mod state;
 mod wallpaper_queue;
+mod watcher;
 
 use cleanup::Cleanup;
+use state::StateFile;
 use wallpaper_queue::WallpaperQueue;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let bg_dir = std::env::home_dir()
-        .ok_or(anyhow!("Could not get home directory"))?
-        .join("backgrounds");
+    let config = Config::load_default()?;
+    config
+        .transition
+        .validate()
+        .map_err(|err| anyhow::anyhow!("Invalid transition in config: {err}"))?;
+    let schedule = Schedule::new(config.schedule.clone(), config.location)
+        .map_err(|err| anyhow::anyhow!("Invalid schedule in config: {err}"))?;
 
+    let state = StateFile::load(StateFile::default_path()?);
     let wallpaper_queue = WallpaperQueue::builder()
-        .with_initial_queue_from_directory(&bg_dir)
-        .await
+        .with_sources(config.directories.clone())
+        .with_interval(config.interval)
+        .with_align(config.align)
+        .with_mode(config.mode)
+        .with_seed(config.seed)
+        .with_output_mode(config.output_mode)
+        .with_backend(config.backend.build()?)
+        .with_transition(config.transition.clone())
+        .with_state_file(state.clone())
         .dbg()
-        .build();
+        .build()
+        .await?;
+
+    let _ = wallpaper_queue
+        .start_watching()
+        .await
+        .inspect_err(|err| eprintln!("Failed to watch wallpaper directories: {err}"));
+
+    wallpaper_queue.start_schedule(schedule);
 
-    let args: Vec<String> = std::env::args().collect();
-    if let Some(bg) = args.get(1).map(|s| s.to_string()) {
+    // A wallpaper given on the command line takes precedence over the configured one
+    let startup_wallpaper = std::env::args()
+        .nth(1)
+        .map(std::path::PathBuf::from)
+        .or(config.startup_wallpaper());
+    if let Some(bg) = startup_wallpaper {
+        let bg = bg
+            .canonicalize()
+            .unwrap_or(bg)
+            .to_string_lossy()
+            .to_string();
         let wq = wallpaper_queue.clone();
         tokio::spawn(async move {
-            tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-            let _ = wq.switch_to_wallpaper(&bg).await;
+            wq.wait_until_ready().await;
+            let _ = wq
+                .switch_to_wallpaper(&bg, None, SwitchReason::Startup)
+                .await
+                .inspect_err(|err| eprintln!("Failed to apply startup wallpaper: {err}"));
         });
     }
 
-    let unix_socket_path = UnixSocketPath::RuntimeDir("swwwitch.sock").to_pathbuf()?;
+    let unix_socket_path = config.socket_path()?;
 
     let socket = setup_listener(&unix_socket_path).await;
 
-    Cleanup { unix_socket_path }.bind_os_signals();
+    Cleanup {
+        unix_socket_path,
+        state,
+    }
+    .bind_os_signals();
 
     ipc::run(socket, wallpaper_queue).await;
 
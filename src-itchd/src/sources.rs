@@ -0,0 +1,276 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use swww_itch_shared::{
+    config::WallpaperSource,
+    message::{ItchError, ItchResult},
+};
+use walkdir::WalkDir;
+
+use crate::image::{self, Probed};
+
+/// A [`WallpaperSource`] resolved for matching paths against it.
+#[derive(Debug, Clone)]
+pub struct SourceFilter {
+    root: PathBuf,
+    /// Depth of wallpapers relative to `root` in [`WalkDir`] terms, where files directly in the
+    /// root are at depth 1.
+    max_depth: usize,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl SourceFilter {
+    pub fn new(source: &WallpaperSource) -> ItchResult<Self> {
+        let max_depth = match (source.recursive, source.max_depth) {
+            (false, _) => 1,
+            (true, Some(depth)) => depth.saturating_add(1),
+            (true, None) => usize::MAX,
+        };
+
+        let include = match source.include.is_empty() {
+            true => None,
+            false => Some(glob_set(&source.include)?),
+        };
+
+        // Queue entries are canonical paths, so matches must be made against the canonical root.
+        let root = source.root();
+        let root = root.canonicalize().unwrap_or(root);
+
+        Ok(Self {
+            root,
+            max_depth,
+            include,
+            exclude: glob_set(&source.exclude)?,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether a file at `path` below the root is within the depth limit and matches the
+    /// patterns. Whether it is an image is up to [`image::probe`].
+    pub fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+
+        if relative.components().count() > self.max_depth {
+            return false;
+        }
+
+        if self.exclude.is_match(relative) {
+            return false;
+        }
+
+        if let Some(include) = self.include.as_ref()
+            && !include.is_match(relative)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// Whether files in the directory at `path` can still be within the depth limit.
+    pub fn descends_into(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.components().count() < self.max_depth,
+            Err(_) => false,
+        }
+    }
+
+    /// `dir` and the directories below it which may contain wallpapers.
+    pub fn directories(&self, dir: &Path) -> Vec<PathBuf> {
+        self.walk(dir)
+            .filter(|entry| entry.file_type().is_dir())
+            .map(|entry| entry.into_path())
+            .filter(|dir| self.descends_into(dir))
+            .collect()
+    }
+
+    /// Files in `dir`, which is the root or one of its subdirectories.
+    pub fn scan(&self, dir: &Path) -> Vec<Probed> {
+        self.walk(dir)
+            .filter(|entry| !entry.file_type().is_dir())
+            .filter(|entry| self.matches(entry.path()))
+            .map(|entry| image::probe(entry.path()))
+            .collect()
+    }
+
+    fn walk(&self, dir: &Path) -> impl Iterator<Item = walkdir::DirEntry> {
+        let depth_below_root = dir
+            .strip_prefix(&self.root)
+            .map(|relative| relative.components().count())
+            .unwrap_or(0);
+
+        // Following symlinks lets walkdir detect loops, which it reports as errors.
+        WalkDir::new(dir)
+            .follow_links(true)
+            .sort_by_file_name()
+            .max_depth(self.max_depth.saturating_sub(depth_below_root))
+            .into_iter()
+            .filter_map(|entry| {
+                entry
+                    .inspect_err(|err| eprintln!("[sources.rs]: Skipping entry: {err}"))
+                    .ok()
+            })
+    }
+}
+
+/// Files found in all of `sources`, in source order and without duplicates.
+pub fn scan(sources: &[SourceFilter]) -> Vec<Probed> {
+    let mut seen = HashSet::new();
+
+    sources
+        .iter()
+        .flat_map(|source| source.scan(source.root()))
+        .filter(|probed| seen.insert(probed.path().to_string()))
+        .collect()
+}
+
+pub fn filters(sources: &[WallpaperSource]) -> ItchResult<Vec<SourceFilter>> {
+    sources.iter().map(SourceFilter::new).collect()
+}
+
+fn glob_set(patterns: &[String]) -> ItchResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|err| ItchError::InvalidSource(format!("Invalid pattern {pattern}: {err}")))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map_err(|err| ItchError::InvalidSource(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use crate::wallpaper_queue::wallpapers;
+
+    use super::*;
+
+    /// A source directory holding an image at each of `paths`, which may be in subdirectories.
+    fn tree(test: &str, paths: &[&str]) -> PathBuf {
+        let dir = wallpapers(test, &[]);
+        let image = wallpapers(&format!("{test}-image"), &["image.png"]).join("image.png");
+        for path in paths {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::copy(&image, path).unwrap();
+        }
+        dir
+    }
+
+    /// Paths of the files found in `source`, relative to `dir`.
+    fn found(dir: &Path, source: WallpaperSource) -> Vec<String> {
+        scan(&[SourceFilter::new(&source).unwrap()])
+            .iter()
+            .map(|probed| {
+                let path = Path::new(probed.path()).strip_prefix(dir).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect()
+    }
+
+    fn source(dir: &Path) -> WallpaperSource {
+        WallpaperSource::new(dir.to_string_lossy())
+    }
+
+    #[test]
+    fn scans_subdirectories_only_when_recursive() {
+        let dir = tree(
+            "sources-recursive",
+            &["a.png", "sub/b.png", "sub/deep/c.png"],
+        );
+
+        assert_eq!(found(&dir, source(&dir)), ["a.png"]);
+        assert_eq!(
+            found(
+                &dir,
+                WallpaperSource {
+                    recursive: true,
+                    ..source(&dir)
+                }
+            ),
+            ["a.png", "sub/b.png", "sub/deep/c.png"]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_maximum_depth() {
+        let dir = tree("sources-depth", &["a.png", "sub/b.png", "sub/deep/c.png"]);
+        let source = WallpaperSource {
+            recursive: true,
+            max_depth: Some(1),
+            ..source(&dir)
+        };
+
+        assert_eq!(found(&dir, source.clone()), ["a.png", "sub/b.png"]);
+
+        let filter = SourceFilter::new(&source).unwrap();
+        assert!(filter.descends_into(&dir.join("sub")));
+        assert!(!filter.descends_into(&dir.join("sub/deep")));
+        assert!(!filter.matches(&dir.join("sub/deep/c.png")));
+        assert_eq!(filter.directories(&dir), [dir.clone(), dir.join("sub")]);
+    }
+
+    #[test]
+    fn filters_files_by_include_and_exclude_patterns() {
+        let dir = tree(
+            "sources-globs",
+            &["a.png", "b.jpg", "private/c.png", "sub/d.png"],
+        );
+        let source = WallpaperSource {
+            recursive: true,
+            include: vec!["*.png".to_string()],
+            exclude: vec!["private/**".to_string()],
+            ..source(&dir)
+        };
+
+        assert_eq!(found(&dir, source), ["a.png", "sub/d.png"]);
+    }
+
+    #[test]
+    fn rejects_an_invalid_pattern() {
+        let source = WallpaperSource {
+            include: vec!["[".to_string()],
+            ..WallpaperSource::new("/tmp")
+        };
+
+        assert!(matches!(
+            SourceFilter::new(&source),
+            Err(ItchError::InvalidSource(_))
+        ));
+    }
+
+    #[test]
+    fn survives_a_symlink_loop() {
+        let dir = tree("sources-loop", &["a.png", "sub/b.png"]);
+        symlink(&dir, dir.join("sub").join("loop")).unwrap();
+        let source = WallpaperSource {
+            recursive: true,
+            ..source(&dir)
+        };
+
+        assert_eq!(found(&dir, source), ["a.png", "sub/b.png"]);
+    }
+
+    #[test]
+    fn finds_nothing_in_a_missing_source() {
+        let dir = tree("sources-missing", &[]).join("missing");
+        let filter = SourceFilter::new(&source(&dir)).unwrap();
+
+        assert!(filter.scan(filter.root()).is_empty());
+        assert!(filter.directories(filter.root()).is_empty());
+    }
+}
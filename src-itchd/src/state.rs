@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use swww_itch_shared::{
+    config::WallpaperSource,
+    message::{HistoryEntry, OutputMode, RotationMode, WallpaperMeta},
+    swww_ffi::TransitionOptions,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::spawn_blocking,
+};
+
+use crate::wallpaper_queue::Playlist;
+
+/// Daemon state which survives restarts.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PersistedState {
+    /// Rotation interval in seconds of the active playlist, as last set over IPC.
+    pub interval: Option<u64>,
+    /// Rotation mode of the active playlist, as last set over IPC.
+    pub mode: Option<RotationMode>,
+    /// Order of the active playlist as last arranged.
+    pub queue: Option<Vec<String>>,
+    /// The wallpaper the scheduler will apply next.
+    pub upcoming: Option<String>,
+    /// How wallpapers are spread over the outputs, as last set over IPC.
+    pub output_mode: Option<OutputMode>,
+    /// The wallpaper to apply next on every output but the first, when outputs rotate
+    /// independently.
+    pub outputs: Option<BTreeMap<String, String>>,
+    pub active_playlist: Option<String>,
+    /// Every playlist. The order, interval and mode of the active one are stored above.
+    pub playlists: Option<Vec<Playlist>>,
+    /// Transitions set over IPC for single wallpapers, by wallpaper.
+    pub transitions: Option<BTreeMap<String, TransitionOptions>>,
+    /// Display durations, weights and pins set over IPC for single wallpapers, by wallpaper.
+    pub meta: Option<BTreeMap<String, WallpaperMeta>>,
+    /// Wallpaper sources as last set over IPC.
+    pub sources: Option<Vec<WallpaperSource>>,
+    /// Wallpapers shown lately, oldest first.
+    pub history: Option<Vec<HistoryEntry>>,
+}
+
+/// Writes waiting for the writer task. Updates beyond that are covered by a write which is
+/// already waiting.
+const PENDING_WRITES: usize = 8;
+
+/// In-memory copy of [`PersistedState`] which is written back to disk after every update.
+#[derive(Clone, Debug)]
+pub struct StateFile {
+    state: Arc<Mutex<PersistedState>>,
+    /// Asks the writer task to save the state, telling the sender once it did if given one.
+    writer: Option<mpsc::Sender<Option<oneshot::Sender<()>>>>,
+}
+
+impl StateFile {
+    /// `$XDG_STATE_HOME/swww-itch/state.json`, falling back to `~/.local/state`.
+    pub fn default_path() -> anyhow::Result<PathBuf> {
+        let state_home = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => std::env::home_dir()
+                .ok_or(anyhow!("Could not get home directory"))?
+                .join(".local/state"),
+        };
+
+        Ok(state_home.join("swww-itch").join("state.json"))
+    }
+
+    /// A missing or unreadable file starts out with the default state. Must be called from
+    /// within the runtime, which the state is saved on.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        let state = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                eprintln!("Ignoring malformed state file {}: {err}", path.display());
+                PersistedState::default()
+            }),
+            Err(_) => PersistedState::default(),
+        };
+
+        let state = Arc::new(Mutex::new(state));
+        let (writer, requests) = mpsc::channel(PENDING_WRITES);
+        tokio::spawn(write_behind(path, state.clone(), requests));
+
+        Self {
+            state,
+            writer: Some(writer),
+        }
+    }
+
+    /// State which is never written to disk.
+    pub fn in_memory() -> Self {
+        Self {
+            state: Default::default(),
+            writer: None,
+        }
+    }
+
+    pub fn get(&self) -> PersistedState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Change the state and have it saved in the background.
+    pub fn update(&self, f: impl FnOnce(&mut PersistedState)) {
+        f(&mut self.state.lock().unwrap());
+
+        if let Some(writer) = &self.writer {
+            // A full queue holds a write which will see this change as well.
+            let _ = writer.try_send(None);
+        }
+    }
+
+    /// Wait until every update so far is on disk.
+    pub async fn flush(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+
+        let (tx, rx) = oneshot::channel();
+        if writer.send(Some(tx)).await.is_ok() {
+            let _ = rx.await;
+        }
+    }
+}
+
+/// Save `state` to `path` whenever asked to, one write at a time and off the async threads.
+async fn write_behind(
+    path: PathBuf,
+    state: Arc<Mutex<PersistedState>>,
+    mut requests: mpsc::Receiver<Option<oneshot::Sender<()>>>,
+) {
+    while let Some(request) = requests.recv().await {
+        let mut waiting: Vec<oneshot::Sender<()>> = request.into_iter().collect();
+        // Requests which came in meanwhile are covered by this write.
+        while let Ok(request) = requests.try_recv() {
+            waiting.extend(request);
+        }
+
+        let snapshot = state.lock().unwrap().clone();
+        let target = path.clone();
+        let result = spawn_blocking(move || write_atomic(&target, &snapshot))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result);
+        if let Err(err) = result {
+            eprintln!("Failed to save state to {}: {err}", path.display());
+        }
+
+        for tx in waiting {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Write to a sibling temporary file and rename it over `path`, so a crash never leaves a
+/// half-written state file behind.
+fn write_atomic(path: &Path, state: &PersistedState) -> anyhow::Result<()> {
+    let dir = path
+        .parent()
+        .ok_or(anyhow!("State file path has no parent directory"))?;
+    fs::create_dir_all(dir)?;
+
+    let tmp_path = path.with_extension("json.tmp");
+    let mut tmp = fs::File::create(&tmp_path)?;
+    tmp.write_all(&serde_json::to_vec_pretty(state)?)?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where a test keeps its state file, which does not exist yet.
+    fn state_path(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("swww-itchd-{}-state-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("state.json")
+    }
+
+    #[tokio::test]
+    async fn restores_what_was_saved() {
+        let path = state_path("restore");
+        let state = StateFile::load(&path);
+        assert!(state.get().queue.is_none());
+
+        state.update(|state| {
+            state.interval = Some(90);
+            state.mode = Some(RotationMode::Shuffle);
+        });
+        state.update(|state| state.queue = Some(vec!["/a.png".into(), "/b.png".into()]));
+        state.flush().await;
+
+        let restored = StateFile::load(&path).get();
+        assert_eq!(restored.interval, Some(90));
+        assert_eq!(restored.mode, Some(RotationMode::Shuffle));
+        assert_eq!(restored.queue, Some(vec!["/a.png".into(), "/b.png".into()]));
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn starts_over_from_a_corrupt_state_file() {
+        let path = state_path("corrupt");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"{\"interval\": 90, \"queue\": [").unwrap();
+
+        let state = StateFile::load(&path);
+        assert!(state.get().interval.is_none());
+
+        // The next update replaces it with one which can be read again.
+        state.update(|state| state.interval = Some(60));
+        state.flush().await;
+        assert_eq!(StateFile::load(&path).get().interval, Some(60));
+    }
+
+    #[tokio::test]
+    async fn logs_and_carries_on_when_the_state_can_not_be_written() {
+        // A file where the directory should be.
+        let path = state_path("unwritable");
+        fs::create_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+        fs::write(path.parent().unwrap(), b"").unwrap();
+
+        let state = StateFile::load(path.join("state.json"));
+        state.update(|state| state.interval = Some(60));
+        state.flush().await;
+        assert_eq!(state.get().interval, Some(60));
+
+        fs::remove_file(path.parent().unwrap()).unwrap();
+    }
+}
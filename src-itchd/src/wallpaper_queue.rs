@@ -1,18 +1,53 @@
 // called wallpaper here instead of background to not be confused with the verb background.
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use anyhow::anyhow;
-use swww_itch_shared::message::Position;
+use swww_itch_shared::{
+    backend::WallpaperBackend,
+    config::WallpaperSource,
+    duration::format_duration,
+    message::{
+        BackendStatus, Event, FailedWallpaper, HistoryEntry, ImageInfo, ItchError, ItchResult,
+        OutputInfo, OutputMode, PlaylistInfo, Position, RejectedFile, RotationMode, Status,
+        SwitchReason, WallpaperMeta,
+    },
+    schedule::{Schedule, ScheduleRule},
+    sun::SolarPhase,
+    swww_ffi::TransitionOptions,
+};
 use tokio::{
-    sync::{Mutex, mpsc},
+    sync::{Mutex, broadcast, mpsc},
+    task::spawn_blocking,
     time::Duration,
 };
 
+use crate::{
+    image::Images,
+    sources,
+    state::StateFile,
+    watcher::{self, WatchHandle},
+};
+
 mod builder;
+mod clock;
+mod failures;
+mod history;
+mod outputs;
+mod playlists;
+mod rotation;
 mod scheduler;
 
+#[cfg(test)]
+pub(crate) use scheduler::tests::wallpapers;
+
 pub use builder::WallpaperQueueBuilder;
+use clock::{Clock, Deadline};
+use failures::{Backoff, Failures, MAX_FAILURES};
+use history::History;
+use outputs::Outputs;
+pub use playlists::Playlist;
+use playlists::{Playlists, sync_with_sources};
+use rotation::Rotation;
 use scheduler as sch;
 
 #[derive(Clone)]
@@ -20,17 +55,59 @@ pub struct WallpaperQueue {
     pub queue: Arc<Mutex<Queue>>,
     pub scheduler: SchedulerRemote,
     pub current_index: Arc<Mutex<usize>>,
+    pub interval: Arc<Mutex<Duration>>,
+    pub mode: Arc<Mutex<RotationMode>>,
+    pub events: broadcast::Sender<Event>,
+    pub sources: Arc<Mutex<Vec<WallpaperSource>>>,
+    pub images: Arc<Mutex<Images>>,
+    pub playlists: Arc<Mutex<Playlists>>,
+    watcher: Arc<Mutex<Option<WatchHandle>>>,
+    backend: Arc<dyn WallpaperBackend>,
+    failures: Arc<std::sync::Mutex<Failures>>,
+    history: Arc<std::sync::Mutex<History>>,
+    transition: TransitionOptions,
+    state: StateFile,
+    clock: Clock,
 }
 
+/// Longest time between two looks at the schedule.
+const SCHEDULE_RECHECK: Duration = Duration::from_secs(60);
+
 pub struct Queue {
     v: Vec<String>,
+    /// Transitions used in place of the default one, by wallpaper.
+    transitions: BTreeMap<String, TransitionOptions>,
+    /// Display durations, weights and pins, by wallpaper.
+    meta: BTreeMap<String, WallpaperMeta>,
 }
 
 struct Scheduler {
     queue: Arc<Mutex<Queue>>,
     command_rx: mpsc::Receiver<sch::Command>,
-    interval: Duration,
+    interval: Arc<Mutex<Duration>>,
+    /// Whether switches happen on multiples of the interval counted from midnight.
+    align: bool,
+    clock: Clock,
+    rotation: Rotation,
+    outputs: Outputs,
+    backend: Arc<dyn WallpaperBackend>,
+    failures: Arc<std::sync::Mutex<Failures>>,
+    history: Arc<std::sync::Mutex<History>>,
+    /// Delay before trying again while the backend is not ready.
+    backoff: Backoff,
+    transition: TransitionOptions,
     current_index: Arc<Mutex<usize>>,
+    events: broadcast::Sender<Event>,
+    state: StateFile,
+    /// Time left until the next switch, frozen while the rotation is paused.
+    paused: Option<Duration>,
+    /// Wallpaper the rotation showed last, on the first output if outputs differ.
+    shown: Option<String>,
+    /// Why the switch which was asked for is wanted, until it happened.
+    requested: Option<SwitchReason>,
+    rule: Option<sch::ActiveRule>,
+    /// How light it is outside, if the sun is followed.
+    phase: Option<SolarPhase>,
 }
 
 #[derive(Clone)]
@@ -43,69 +120,886 @@ impl WallpaperQueue {
         WallpaperQueueBuilder::new()
     }
 
-    pub fn new(initial_queue: Vec<String>) -> Self {
-        let queue = Arc::new(Mutex::new(Queue::new(Some(initial_queue))));
-        let current_index = Arc::new(Mutex::new(0));
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        initial_queue: Queue,
+        initial_index: usize,
+        interval: Duration,
+        align: bool,
+        rotation: Rotation,
+        outputs: Outputs,
+        backend: Arc<dyn WallpaperBackend>,
+        transition: TransitionOptions,
+        sources: Vec<WallpaperSource>,
+        images: Images,
+        playlists: Playlists,
+        history: History,
+        state: StateFile,
+        clock: Clock,
+    ) -> Self {
+        let queue = Arc::new(Mutex::new(initial_queue));
+        let current_index = Arc::new(Mutex::new(initial_index));
+        let interval = Arc::new(Mutex::new(interval));
+        let mode = rotation.mode();
+        let failures = Arc::new(std::sync::Mutex::new(Failures::default()));
+        let history = Arc::new(std::sync::Mutex::new(history));
+        let (events, _) = broadcast::channel(32);
 
         Self {
             queue: queue.clone(),
-            scheduler: Scheduler::start(queue, current_index.clone()),
+            scheduler: Scheduler::start(
+                queue,
+                current_index.clone(),
+                interval.clone(),
+                rotation,
+                align,
+                outputs,
+                backend.clone(),
+                failures.clone(),
+                history.clone(),
+                transition.clone(),
+                events.clone(),
+                state.clone(),
+                clock.clone(),
+            ),
             current_index,
+            interval,
+            mode,
+            events,
+            sources: Arc::new(Mutex::new(sources)),
+            images: Arc::new(Mutex::new(images)),
+            playlists: Arc::new(Mutex::new(playlists)),
+            watcher: Default::default(),
+            backend,
+            failures,
+            history,
+            transition,
+            state,
+            clock,
         }
     }
 
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
     pub async fn get_queue(&self) -> Vec<String> {
         self.queue.lock().await.v.to_owned()
     }
 
-    pub async fn switch_to_wallpaper(&self, bg: &str) -> anyhow::Result<()> {
+    /// Outputs the backend draws on, in the order spanned and independent outputs are filled.
+    pub async fn get_outputs(&self) -> ItchResult<Vec<OutputInfo>> {
+        self.backend
+            .outputs()
+            .await
+            .map_err(|err| ItchError::BackendFailed(err.to_string()))
+    }
+
+    /// Wait until the backend can show wallpapers, trying again with a growing delay.
+    pub async fn wait_until_ready(&self) {
+        let mut backoff = Backoff::new();
+        while let Err(err) = self.backend.ready().await {
+            let retry = backoff.next();
+            eprintln!(
+                "Wallpaper backend is not ready, retrying in {}: {err}",
+                format_duration(retry)
+            );
+            tokio::time::sleep(retry).await;
+        }
+    }
+
+    /// Show `bg` right away, on `output` only if given.
+    pub async fn switch_to_wallpaper(
+        &self,
+        bg: &str,
+        output: Option<String>,
+        reason: SwitchReason,
+    ) -> ItchResult<()> {
         let lock = self.queue.lock().await;
 
         let bg_index = lock
             .v
             .iter()
             .position(|v| v.as_str() == bg)
-            .ok_or(anyhow!("Background is not in queue"))?;
+            .ok_or_else(|| ItchError::NotInQueue(bg.to_string()))?;
 
         drop(lock);
 
+        match output {
+            Some(output) => {
+                let outputs = self.get_outputs().await?;
+                if !outputs.iter().any(|known| known.name == output) {
+                    return Err(ItchError::UnknownOutput(output));
+                }
+
+                self.scheduler.switch_output(bg_index, output, reason).await
+            }
+            None => {
+                self.scheduler
+                    .reset_timeout_and_set_index(bg_index, reason)
+                    .await
+            }
+        }
+        .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    pub async fn get_interval(&self) -> Duration {
+        *self.interval.lock().await
+    }
+
+    pub async fn set_interval(&self, interval: Duration) -> ItchResult<()> {
+        if interval.is_zero() {
+            return Err(ItchError::InvalidInterval(
+                "Interval must be longer than zero".to_string(),
+            ));
+        }
+
+        self.scheduler
+            .set_interval(interval)
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)?;
+
+        println!("Interval set to {}", format_duration(interval));
+        self.state
+            .update(|state| state.interval = Some(interval.as_secs()));
+
+        Ok(())
+    }
+
+    pub async fn get_mode(&self) -> RotationMode {
+        *self.mode.lock().await
+    }
+
+    pub async fn set_mode(&self, mode: RotationMode) -> ItchResult<()> {
+        self.scheduler
+            .set_mode(mode)
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)?;
+
+        println!("Rotation mode set to {mode}");
+        self.state.update(|state| state.mode = Some(mode));
+
+        Ok(())
+    }
+
+    pub async fn set_output_mode(&self, mode: OutputMode) -> ItchResult<()> {
+        self.scheduler
+            .set_output_mode(mode)
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)?;
+
+        println!("Output mode set to {mode}");
+        self.state.update(|state| state.output_mode = Some(mode));
+
+        Ok(())
+    }
+
+    pub async fn next_wallpaper(&self) -> ItchResult<()> {
+        self.scheduler
+            .next()
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    pub async fn previous_wallpaper(&self) -> ItchResult<()> {
+        self.scheduler
+            .previous()
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    /// Show the wallpaper from before the current one in the history again, whatever the
+    /// rotation mode. Wallpapers which left the queue are passed over.
+    pub async fn back(&self) -> ItchResult<()> {
+        let index = {
+            let queue = self.queue.lock().await;
+            let mut history = self.history.lock().unwrap();
+
+            let previous = history
+                .go_back(|bg| queue.v.iter().any(|v| v == bg))
+                .ok_or(ItchError::NoHistory)?;
+
+            queue
+                .v
+                .iter()
+                .position(|v| *v == previous)
+                .expect("History only goes back to queued wallpapers")
+        };
+
+        self.scheduler
+            .reset_timeout_and_set_index(index, SwitchReason::Manual)
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    pub async fn pause(&self) -> ItchResult<()> {
+        self.scheduler
+            .pause()
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    pub async fn resume(&self) -> ItchResult<()> {
+        self.scheduler
+            .resume()
+            .await
+            .map_err(|_| ItchError::SchedulerUnavailable)
+    }
+
+    pub async fn get_sources(&self) -> Vec<WallpaperSource> {
+        self.sources.lock().await.clone()
+    }
+
+    /// Replace the wallpaper sources, rescan them and watch them instead of the previous ones.
+    /// Wallpapers found in both keep their place in the queue.
+    pub async fn set_sources(&self, new_sources: Vec<WallpaperSource>) -> ItchResult<()> {
+        let filters = sources::filters(&new_sources)?;
+
+        let probed = spawn_blocking(move || sources::scan(&filters))
+            .await
+            .map_err(|err| ItchError::InvalidSource(err.to_string()))?;
+
+        let (found, rejected) = {
+            let mut images = self.images.lock().await;
+            let known = images.rejected();
+            images.clear();
+            let found = images.extend(probed);
+            let rejected: Vec<RejectedFile> = images
+                .rejected()
+                .into_iter()
+                .filter(|rejected| !known.contains(rejected))
+                .collect();
+            (found, rejected)
+        };
+
+        for rejected in rejected {
+            let _ = self.events.send(Event::FileRejected(rejected));
+        }
+
+        self.modify_playlists(|queue, follows_sources| {
+            let synced = sync_with_sources(queue.clone(), &found, follows_sources);
+            if synced == *queue {
+                return false;
+            }
+            *queue = synced;
+            true
+        })
+        .await;
+
+        *self.sources.lock().await = new_sources.clone();
+        self.state.update(|state| state.sources = Some(new_sources));
+
+        let _ = self
+            .start_watching()
+            .await
+            .inspect_err(|err| eprintln!("Failed to watch wallpaper directories: {err}"));
+
+        Ok(())
+    }
+
+    /// Scan the current sources again, for when the watcher lost track of what changed in them.
+    pub async fn rescan_sources(&self) -> ItchResult<()> {
+        self.set_sources(self.get_sources().await).await
+    }
+
+    /// Keep the queue in sync with the current sources, replacing any previous watcher.
+    pub async fn start_watching(&self) -> anyhow::Result<()> {
+        let filters = sources::filters(&self.get_sources().await)?;
+
+        let mut watcher = self.watcher.lock().await;
+        // Stop the old watcher first so its events can not interleave with the new one's.
+        *watcher = None;
+        *watcher = Some(watcher::watch(filters, self.clone())?);
+
+        Ok(())
+    }
+
+    /// Follow the first rule of `schedule` which applies, playing its playlist and switching at
+    /// its times, for as long as the daemon runs. With a location, wallpapers and playlists
+    /// tagged with a phase of the sun are played during that phase.
+    pub fn start_schedule(&self, schedule: Schedule) {
+        if schedule.is_empty() && !schedule.follows_sun() {
+            return;
+        }
+
+        let wq = self.clone();
+        tokio::spawn(async move {
+            let mut active = None;
+            let mut sun = None;
+            loop {
+                let now = wq.clock.now();
+                let rule = schedule.active_rule(now);
+                let phase = schedule.phase(now);
+
+                let rule_changed = active != Some(rule.map(|(i, _)| i));
+                if rule_changed {
+                    active = Some(rule.map(|(i, _)| i));
+                    wq.follow_rule(rule.map(|(_, rule)| rule)).await;
+                }
+
+                let phase_changed = sun != Some(phase);
+                if phase_changed {
+                    sun = Some(phase);
+                    wq.follow_phase(phase).await;
+                }
+
+                // A rule's own playlist wins over the one tagged with the phase.
+                let rule_playlist = rule.is_some_and(|(_, rule)| rule.playlist.is_some());
+                if (rule_changed || phase_changed) && !rule_playlist {
+                    wq.play_tagged_playlist(phase).await;
+                }
+
+                // Look again every now and then in case the clock jumped, e.g. after a suspend.
+                let wait = [schedule.next_change(now), schedule.next_phase_change(now)]
+                    .into_iter()
+                    .flatten()
+                    .min()
+                    .and_then(|next| (next - now).to_std().ok())
+                    .unwrap_or(SCHEDULE_RECHECK)
+                    .min(SCHEDULE_RECHECK);
+                tokio::time::sleep(wait).await;
+            }
+        });
+    }
+
+    async fn follow_rule(&self, rule: Option<&ScheduleRule>) {
+        match rule {
+            Some(rule) => println!("Schedule rule {} applies", rule.label()),
+            None => println!("No schedule rule applies"),
+        }
+
+        if let Some(playlist) = rule.and_then(|rule| rule.playlist.as_deref()) {
+            let _ = self
+                .activate_playlist(playlist)
+                .await
+                .inspect_err(|err| eprintln!("Failed to activate scheduled playlist: {err}"));
+        }
+
+        let active = rule.map(|rule| sch::ActiveRule {
+            label: rule.label(),
+            at: rule.at.clone(),
+        });
+        let _ = self
+            .scheduler
+            .set_rule(active)
+            .await
+            .inspect_err(|err| eprintln!("Failed to apply schedule rule: {err}"));
+    }
+
+    async fn follow_phase(&self, phase: Option<SolarPhase>) {
+        if let Some(phase) = phase {
+            println!("It is {phase} outside");
+        }
+
+        let _ = self
+            .scheduler
+            .set_phase(phase)
+            .await
+            .inspect_err(|err| eprintln!("Failed to follow the sun: {err}"));
+    }
+
+    /// Activate the playlist tagged with `phase`, if any.
+    async fn play_tagged_playlist(&self, phase: Option<SolarPhase>) {
+        let playlists = self.playlists.lock().await;
+        let Some(playlist) = phase.and_then(|phase| playlists.tagged(phase)) else {
+            return;
+        };
+        let name = playlist.name.clone();
+        drop(playlists);
+
+        let _ = self
+            .activate_playlist(&name)
+            .await
+            .inspect_err(|err| eprintln!("Failed to activate playlist of the phase: {err}"));
+    }
+
+    pub async fn list_playlists(&self) -> Vec<PlaylistInfo> {
+        let playlists = self.playlists.lock().await;
+        let len = self.queue.lock().await.v.len();
+
+        playlists.info(len, self.get_interval().await, self.get_mode().await)
+    }
+
+    /// Create a playlist with the current interval and rotation mode. Without `wallpapers` it
+    /// holds every known wallpaper and follows the sources.
+    pub async fn create_playlist(
+        &self,
+        name: String,
+        wallpapers: Option<Vec<String>>,
+    ) -> ItchResult<()> {
+        let mut playlists = self.playlists.lock().await;
+        playlists.check_name(&name)?;
+
+        let images = self.images.lock().await;
+        let follows_sources = wallpapers.is_none();
+        let queue = match wallpapers {
+            Some(wallpapers) => {
+                if let Some(unknown) = wallpapers.iter().find(|bg| images.get(bg).is_none()) {
+                    return Err(ItchError::InvalidPath(unknown.clone()));
+                }
+                wallpapers
+            }
+            None => images.wallpapers(),
+        };
+        drop(images);
+
+        println!("Created playlist {name}");
+        playlists.entries.push(Playlist::new(
+            name,
+            follows_sources,
+            queue,
+            self.get_interval().await,
+            self.get_mode().await,
+        ));
+        playlists.save(&self.state);
+
+        self.playlists_changed(&playlists).await;
+        Ok(())
+    }
+
+    pub async fn delete_playlist(&self, name: &str) -> ItchResult<()> {
+        let mut playlists = self.playlists.lock().await;
+        playlists.get(name)?;
+
+        if playlists.active == name {
+            return Err(ItchError::PlaylistActive(name.to_string()));
+        }
+
+        println!("Deleted playlist {name}");
+        playlists.entries.retain(|playlist| playlist.name != name);
+        playlists.save(&self.state);
+
+        self.playlists_changed(&playlists).await;
+        Ok(())
+    }
+
+    pub async fn rename_playlist(&self, from: &str, to: String) -> ItchResult<()> {
+        let mut playlists = self.playlists.lock().await;
+        playlists.get(from)?;
+        playlists.check_name(&to)?;
+
+        println!("Renamed playlist {from} -> {to}");
+        if playlists.active == from {
+            playlists.active = to.clone();
+        }
+        playlists.get_mut(from)?.name = to;
+        playlists.save(&self.state);
+
+        self.playlists_changed(&playlists).await;
+        Ok(())
+    }
+
+    /// Swap the order, interval and rotation mode of the active playlist for the ones of
+    /// `name`.
+    pub async fn activate_playlist(&self, name: &str) -> ItchResult<()> {
+        let mut playlists = self.playlists.lock().await;
+        let target = playlists.get(name)?.clone();
+
+        if playlists.active == name {
+            return Ok(());
+        }
+
+        let interval = self.get_interval().await;
+        let mode = self.get_mode().await;
+        {
+            let queue = self.queue.lock().await;
+            let index = self.current_index.lock().await;
+
+            let active = playlists.active.clone();
+            let previous = playlists.get_mut(&active)?;
+            previous.upcoming = queue.v.get(*index).cloned();
+            previous.queue = queue.v.clone();
+            previous.interval = interval.as_secs();
+            previous.mode = mode;
+        }
+
+        playlists.active = target.name.clone();
+        playlists.save(&self.state);
+        self.state.update(|state| {
+            state.interval = Some(target.interval);
+            state.mode = Some(target.mode);
+        });
+        drop(playlists);
+
+        // Only answer once the queue was swapped, so that the caller sees the new one.
         self.scheduler
-            .reset_timeout_and_set_index(bg_index)
+            .activate_playlist(target)
             .await
-            .expect("Scheduler should be available");
+            .ok_or(ItchError::SchedulerUnavailable)?;
+        println!("Activated playlist {name}");
+
+        self.playlists_changed(&*self.playlists.lock().await).await;
+
+        Ok(())
+    }
+
+    /// Activate `name` whenever `phase` begins, or never again by itself without a phase. Takes
+    /// effect from the next change of phase.
+    pub async fn set_playlist_phase(
+        &self,
+        name: &str,
+        phase: Option<SolarPhase>,
+    ) -> ItchResult<()> {
+        let mut playlists = self.playlists.lock().await;
+        playlists.get_mut(name)?.phase = phase;
+        match phase {
+            Some(phase) => println!("Playlist {name} is played at {phase}"),
+            None => println!("Playlist {name} is no longer played by phase"),
+        }
+        playlists.save(&self.state);
+
+        self.playlists_changed(&playlists).await;
+        Ok(())
+    }
+
+    async fn playlists_changed(&self, playlists: &Playlists) {
+        let len = self.queue.lock().await.v.len();
+        let info = playlists.info(len, self.get_interval().await, self.get_mode().await);
+
+        let _ = self.events.send(Event::PlaylistsChanged(info));
+    }
+
+    /// The transition `bg` is shown with, or the default one without `bg`.
+    pub async fn get_transition(&self, bg: Option<&str>) -> ItchResult<TransitionOptions> {
+        let Some(bg) = bg else {
+            return Ok(self.transition.clone());
+        };
+
+        let queue = self.queue.lock().await;
+        if self.images.lock().await.get(bg).is_none() {
+            return Err(ItchError::NotInQueue(bg.to_string()));
+        }
+
+        Ok(queue.transition(bg, &self.transition).clone())
+    }
+
+    /// Show `bg` with `transition` from now on, or with the default one again if `None`.
+    pub async fn set_transition(
+        &self,
+        bg: &str,
+        transition: Option<TransitionOptions>,
+    ) -> ItchResult<()> {
+        if self.images.lock().await.get(bg).is_none() {
+            return Err(ItchError::NotInQueue(bg.to_string()));
+        }
+
+        let mut queue = self.queue.lock().await;
+        match transition {
+            Some(transition) => {
+                transition
+                    .validate()
+                    .map_err(ItchError::InvalidTransition)?;
+                println!("Transition for {bg} set to {}", transition.transition_type);
+                queue.transitions.insert(bg.to_string(), transition);
+            }
+            None => {
+                println!("Transition for {bg} reset to the default");
+                queue.transitions.remove(bg);
+            }
+        }
+        queue.save_transitions(&self.state);
+
+        Ok(())
+    }
+
+    pub async fn get_wallpaper_meta(&self, bg: &str) -> ItchResult<WallpaperMeta> {
+        let queue = self.queue.lock().await;
+        if self.images.lock().await.get(bg).is_none() {
+            return Err(ItchError::NotInQueue(bg.to_string()));
+        }
+
+        Ok(queue.meta.get(bg).cloned().unwrap_or_default())
+    }
+
+    /// Replace the display duration, weight, pin and phase of `bg`. They apply from the next
+    /// switch.
+    pub async fn set_wallpaper_meta(&self, bg: &str, meta: WallpaperMeta) -> ItchResult<()> {
+        meta.validate().map_err(ItchError::InvalidWallpaperMeta)?;
+
+        if self.images.lock().await.get(bg).is_none() {
+            return Err(ItchError::NotInQueue(bg.to_string()));
+        }
+
+        let mut queue = self.queue.lock().await;
+        if meta == WallpaperMeta::default() {
+            println!("Settings for {bg} reset to the default");
+            queue.meta.remove(bg);
+        } else {
+            println!("Settings for {bg} changed");
+            queue.meta.insert(bg.to_string(), meta);
+        }
+        queue.save_meta(&self.state);
+
         Ok(())
     }
 
+    /// Show `bg` with `transition` on every output without touching the queue or the countdown.
+    pub async fn preview_transition(
+        &self,
+        bg: &str,
+        transition: &TransitionOptions,
+    ) -> ItchResult<()> {
+        transition
+            .validate()
+            .map_err(ItchError::InvalidTransition)?;
+
+        if self.images.lock().await.get(bg).is_none() {
+            return Err(ItchError::NotInQueue(bg.to_string()));
+        }
+
+        self.backend
+            .set_wallpaper(bg, &[], transition)
+            .await
+            .map_err(|err| ItchError::BackendFailed(err.to_string()))
+    }
+
+    pub async fn get_image_info(&self, bg: &str) -> ItchResult<ImageInfo> {
+        self.images
+            .lock()
+            .await
+            .get(bg)
+            .cloned()
+            .ok_or_else(|| ItchError::NotInQueue(bg.to_string()))
+    }
+
+    pub async fn get_rejected(&self) -> Vec<RejectedFile> {
+        self.images.lock().await.rejected()
+    }
+
+    pub fn get_failures(&self) -> Vec<FailedWallpaper> {
+        self.failures.lock().unwrap().list()
+    }
+
+    pub fn get_history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().unwrap().list()
+    }
+
+    pub async fn get_status(&self) -> ItchResult<Status> {
+        let snapshot = self
+            .scheduler
+            .status()
+            .await
+            .ok_or(ItchError::SchedulerUnavailable)?;
+
+        let (index, upcoming) = {
+            let queue = self.queue.lock().await;
+            let index = snapshot
+                .shown
+                .as_ref()
+                .and_then(|shown| queue.v.iter().position(|v| v == shown));
+            (
+                index,
+                queue.v.get(*self.current_index.lock().await).cloned(),
+            )
+        };
+
+        let error = self.backend.ready().await.err().map(|err| err.to_string());
+        let skipped = self
+            .get_failures()
+            .iter()
+            .filter(|failed| failed.skipped)
+            .count();
+
+        Ok(Status {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            wallpaper: snapshot.shown,
+            index,
+            upcoming,
+            playlist: self.playlists.lock().await.active.clone(),
+            rule: snapshot.rule,
+            mode: self.get_mode().await,
+            output_mode: snapshot.output_mode,
+            interval: self.get_interval().await.as_secs(),
+            duration: snapshot.duration.as_secs(),
+            next_switch: snapshot.remaining.as_secs(),
+            paused: snapshot.paused,
+            backend: BackendStatus {
+                ready: error.is_none(),
+                error,
+                skipped,
+            },
+        })
+    }
+
+    /// Append a wallpaper which appeared in a watched directory to the playlists which follow
+    /// the sources.
+    pub async fn add_wallpaper(&self, bg: String, info: ImageInfo) {
+        self.images.lock().await.accept(bg.clone(), info);
+        // The file changed, so give it another chance.
+        self.failures.lock().unwrap().forget(&bg);
+
+        self.modify_playlists(|queue, follows_sources| {
+            if !follows_sources || queue.contains(&bg) {
+                return false;
+            }
+            queue.push(bg.clone());
+            true
+        })
+        .await;
+    }
+
+    /// Leave out a file in a watched directory which is not a usable image, dropping it from the
+    /// queue if it used to be one.
+    pub async fn reject_file(&self, rejected: RejectedFile) {
+        let path = rejected.path.clone();
+
+        if self.images.lock().await.reject(rejected.clone()) {
+            let _ = self.events.send(Event::FileRejected(rejected));
+        }
+
+        self.modify_playlists(|queue, _| {
+            let len = queue.len();
+            queue.retain(|v| *v != path);
+            queue.len() != len
+        })
+        .await;
+    }
+
+    /// Drop a wallpaper which disappeared from a watched directory.
+    pub async fn remove_wallpaper(&self, bg: &str) {
+        self.images.lock().await.forget(bg);
+        self.failures.lock().unwrap().forget(bg);
+        self.forget_overrides(|v| v == bg).await;
+
+        self.modify_playlists(|queue, _| {
+            let len = queue.len();
+            queue.retain(|v| v.as_str() != bg);
+            queue.len() != len
+        })
+        .await;
+    }
+
+    /// Drop every wallpaper below a directory which disappeared from a watched directory.
+    pub async fn remove_wallpapers_in(&self, dir: &str) {
+        let dir = std::path::Path::new(dir);
+        self.images.lock().await.forget_in(dir);
+        self.forget_overrides(|v| std::path::Path::new(v).starts_with(dir))
+            .await;
+
+        self.modify_playlists(|queue, _| {
+            let len = queue.len();
+            queue.retain(|v| !std::path::Path::new(v).starts_with(dir));
+            queue.len() != len
+        })
+        .await;
+    }
+
+    /// Rename a wallpaper in place, keeping its position in the queue.
+    pub async fn rename_wallpaper(&self, from: &str, to: String, info: ImageInfo) {
+        {
+            let mut images = self.images.lock().await;
+            images.forget(from);
+            images.accept(to.clone(), info);
+        }
+        self.failures.lock().unwrap().forget(from);
+
+        {
+            let mut queue = self.queue.lock().await;
+            if let Some(transition) = queue.transitions.remove(from) {
+                queue.transitions.insert(to.clone(), transition);
+                queue.save_transitions(&self.state);
+            }
+            if let Some(meta) = queue.meta.remove(from) {
+                queue.meta.insert(to.clone(), meta);
+                queue.save_meta(&self.state);
+            }
+        }
+
+        self.modify_playlists(|queue, follows_sources| {
+            if queue.contains(&to) {
+                let len = queue.len();
+                queue.retain(|v| v.as_str() != from);
+                return queue.len() != len;
+            }
+
+            match queue.iter_mut().find(|v| v.as_str() == from) {
+                Some(entry) => *entry = to.clone(),
+                None if follows_sources => queue.push(to.clone()),
+                None => return false,
+            }
+            true
+        })
+        .await;
+    }
+
+    /// Drop the transitions and settings of wallpapers matching `f`, which are gone.
+    async fn forget_overrides(&self, f: impl Fn(&str) -> bool) {
+        let mut queue = self.queue.lock().await;
+
+        let len = queue.transitions.len();
+        queue.transitions.retain(|bg, _| !f(bg));
+        if queue.transitions.len() != len {
+            queue.save_transitions(&self.state);
+        }
+
+        let len = queue.meta.len();
+        queue.meta.retain(|bg, _| !f(bg));
+        if queue.meta.len() != len {
+            queue.save_meta(&self.state);
+        }
+    }
+
+    /// Apply `f` to the order of every playlist, along with whether the playlist follows the
+    /// sources. `f` returns whether it changed anything.
+    async fn modify_playlists(&self, f: impl Fn(&mut Vec<String>, bool) -> bool) {
+        let mut playlists = self.playlists.lock().await;
+
+        let mut changed = false;
+        playlists.for_each_inactive(|playlist| {
+            changed |= f(&mut playlist.queue, playlist.follows_sources);
+        });
+        if changed {
+            playlists.save(&self.state);
+        }
+
+        let follows_sources = playlists.active().follows_sources;
+        self.modify_queue(|queue| f(queue, follows_sources)).await;
+    }
+
+    /// Apply `f` to the queue, which returns whether it changed anything. `current_index` keeps
+    /// pointing at the same wallpaper, or at the one taking its place if it was removed.
+    async fn modify_queue(&self, f: impl FnOnce(&mut Vec<String>) -> bool) {
+        let mut queue = self.queue.lock().await;
+        let mut index = self.current_index.lock().await;
+
+        let upcoming = queue.v.get(*index).cloned();
+
+        if !f(&mut queue.v) {
+            return;
+        }
+
+        *index = upcoming
+            .and_then(|upcoming| queue.v.iter().position(|v| *v == upcoming))
+            .unwrap_or(if *index < queue.v.len() { *index } else { 0 });
+
+        queue.save(&self.state, *index);
+
+        let _ = self.events.send(Event::QueueChanged(queue.v.clone()));
+    }
+
     pub async fn rearrange_wallpaper(
         &self,
         bg: &str,
         before_or_after: &Position,
         target_bg: &str,
-    ) -> anyhow::Result<(usize, usize)> {
-        let i_lock = self.current_index.lock().await;
+    ) -> ItchResult<(usize, usize)> {
         let mut lock = self.queue.lock().await;
-
-        let queued_bg = lock
-            .v
-            .get(*i_lock)
-            .expect("current_index should always point to an existing item")
-            .to_owned();
-        drop(i_lock);
+        let mut i_lock = self.current_index.lock().await;
 
         let bg_index = lock
             .v
             .iter()
             .position(|v| v.ends_with(bg))
-            .ok_or(anyhow!("Background is not in queue"))?;
+            .ok_or_else(|| ItchError::NotInQueue(bg.to_string()))?;
 
         let mut target_index = lock
             .v
             .iter()
             .position(|v| v.as_str().ends_with(target_bg))
-            .ok_or(anyhow!("Target background is not in queue"))?;
+            .ok_or_else(|| ItchError::TargetNotInQueue(target_bg.to_string()))?;
 
         if bg_index == target_index {
-            return Err(anyhow!("Refusing to move wallpaper to the same position"));
+            return Err(ItchError::SamePosition);
         }
 
         match before_or_after {
@@ -124,40 +1018,83 @@ impl WallpaperQueue {
         }
 
         if bg_index == target_index {
-            return Err(anyhow!("Refusing to move wallpaper to the same position"));
+            return Err(ItchError::SamePosition);
         }
 
+        let queued_bg = lock.v.get(*i_lock).cloned();
+
         let item = lock.v.remove(bg_index);
         lock.v.insert(target_index, item);
 
-        let mut i_lock = self.current_index.lock().await;
-
         // Update current_index
-        *i_lock = lock
-            .v
-            .iter()
-            .enumerate()
-            .find_map(|(i, v)| {
-                if v.as_str() == queued_bg.as_str() {
-                    Some(i)
-                } else {
-                    None
-                }
-            })
-            .expect("We have held the lock to queue, therefore queued_bg should be somewhere in the queue");
+        *i_lock = queued_bg
+            .and_then(|queued_bg| lock.v.iter().position(|v| *v == queued_bg))
+            .unwrap_or(0);
 
+        lock.save(&self.state, *i_lock);
         drop(lock);
 
-        // TODO: If current_index is affected, update it
+        let _ = self
+            .events
+            .send(Event::QueueReordered((bg_index, target_index)));
 
         Ok((bg_index, target_index))
     }
 }
 
 impl Queue {
-    pub fn new(v: Option<Vec<String>>) -> Self {
+    pub fn new(
+        v: Option<Vec<String>>,
+        transitions: BTreeMap<String, TransitionOptions>,
+        meta: BTreeMap<String, WallpaperMeta>,
+    ) -> Self {
         Self {
             v: v.unwrap_or_default(),
+            transitions,
+            meta,
         }
     }
+
+    /// The transition to show `bg` with.
+    fn transition<'a>(&'a self, bg: &str, default: &'a TransitionOptions) -> &'a TransitionOptions {
+        self.transitions.get(bg).unwrap_or(default)
+    }
+
+    fn save_transitions(&self, state: &StateFile) {
+        state.update(|state| state.transitions = Some(self.transitions.clone()));
+    }
+
+    /// How long `bg` stays up, if not for the rotation interval.
+    fn duration(&self, bg: &str) -> Option<Duration> {
+        self.meta
+            .get(bg)
+            .and_then(|meta| meta.duration)
+            .map(Duration::from_secs)
+    }
+
+    /// Weight to pick `bg` with in shuffle and random mode.
+    fn weight(&self, bg: &str) -> u32 {
+        self.meta.get(bg).map_or(1, WallpaperMeta::weight).max(1)
+    }
+
+    fn is_pinned(&self, bg: &str) -> bool {
+        self.meta.get(bg).is_some_and(|meta| meta.pinned)
+    }
+
+    /// Whether `bg` may be shown while it is `phase` outside.
+    fn fits(&self, bg: &str, phase: Option<SolarPhase>) -> bool {
+        self.meta.get(bg).is_none_or(|meta| meta.fits(phase))
+    }
+
+    fn save_meta(&self, state: &StateFile) {
+        state.update(|state| state.meta = Some(self.meta.clone()));
+    }
+
+    /// Persist the queue order along with the wallpaper at `current_index`.
+    fn save(&self, state: &StateFile, current_index: usize) {
+        state.update(|state| {
+            state.queue = Some(self.v.clone());
+            state.upcoming = self.v.get(current_index).cloned();
+        });
+    }
 }
@@ -1,39 +1,120 @@
-use std::path::Path;
+use std::collections::HashSet;
+
+use swww_itch_shared::{
+    backend::{SwwwBackend, WallpaperBackend},
+    config::WallpaperSource,
+    message::{OutputMode, RotationMode},
+};
+use tokio::task::spawn_blocking;
+
+use crate::{image::Images, sources};
+
+use super::playlists::{DEFAULT_PLAYLIST, Playlist, Playlists, sync_with_sources};
 
 use super::*;
 
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Debug)]
 pub struct WallpaperQueueBuilder {
-    initial_queue: Vec<String>,
+    sources: Vec<WallpaperSource>,
+    interval: Duration,
+    align: bool,
+    mode: RotationMode,
+    seed: Option<u64>,
+    output_mode: OutputMode,
+    backend: Arc<dyn WallpaperBackend>,
+    transition: TransitionOptions,
+    state: StateFile,
+    clock: Clock,
 }
 
 impl WallpaperQueueBuilder {
     pub(super) fn new() -> Self {
         Self {
-            initial_queue: vec![],
+            sources: vec![],
+            interval: DEFAULT_INTERVAL,
+            align: false,
+            mode: RotationMode::default(),
+            seed: None,
+            output_mode: OutputMode::default(),
+            backend: Arc::new(SwwwBackend),
+            transition: TransitionOptions::default(),
+            state: StateFile::in_memory(),
+            clock: Clock::System,
         }
     }
 
-    pub async fn with_initial_queue_from_directory<P: AsRef<Path>>(mut self, directory: P) -> Self {
-        let mut queue = Vec::new();
-        if let Ok(mut entries) = tokio::fs::read_dir(directory).await {
-            while let Ok(Some(entry)) = entries.next_entry().await {
-                if let Some(bg) = entry
-                    .path()
-                    .canonicalize()
-                    .ok()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .map_or(None, |path| match path.as_str().rsplit_once('.')?.1 {
-                        "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" | "tiff" | "avif"
-                        | "tga" | "pnm" | "farbfeld" => Some(path),
-                        _ => None,
-                    })
-                {
-                    queue.push(bg);
-                }
-            }
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Switch on multiples of the interval counted from midnight instead of counting from the
+    /// last switch.
+    pub fn with_align(mut self, align: bool) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: RotationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Seed for the shuffle and random modes, which are seeded from the OS by default.
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    /// Directories to fill the queue from, in order. Replaced by sources persisted in the state
+    /// file.
+    pub fn with_sources(mut self, sources: Vec<WallpaperSource>) -> Self {
+        self.sources = sources;
+        self
+    }
+
+    /// What shows the wallpapers, swww by default.
+    pub fn with_backend(mut self, backend: Arc<dyn WallpaperBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_transition(mut self, transition: TransitionOptions) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    /// Restores persisted settings, overriding the ones given to the builder, and saves any
+    /// later changes back to the file. The saved queue order is applied in [`Self::build`].
+    pub fn with_state_file(mut self, state: StateFile) -> Self {
+        let saved = state.get();
+        if let Some(interval) = saved.interval.filter(|secs| *secs > 0) {
+            self.interval = Duration::from_secs(interval);
         }
-        self.initial_queue = queue;
+        if let Some(mode) = saved.mode {
+            self.mode = mode;
+        }
+        if let Some(output_mode) = saved.output_mode {
+            self.output_mode = output_mode;
+        }
+        if let Some(sources) = saved.sources {
+            self.sources = sources;
+        }
+        self.state = state;
+        self
+    }
+
+    /// Where the time of day comes from, for aligned switches and the schedule.
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
         self
     }
 
@@ -42,7 +123,138 @@ impl WallpaperQueueBuilder {
         self
     }
 
-    pub fn build(self) -> WallpaperQueue {
-        WallpaperQueue::new(self.initial_queue)
+    /// Scans the sources and restores the saved queue order. Fails if a source has an invalid
+    /// pattern.
+    pub async fn build(self) -> anyhow::Result<WallpaperQueue> {
+        let saved = self.state.get();
+
+        let filters = sources::filters(&self.sources)?;
+        let probed = spawn_blocking(move || sources::scan(&filters)).await?;
+
+        let mut images = Images::default();
+        let found = images.extend(probed);
+        for rejected in images.rejected() {
+            eprintln!("Skipping {}: {}", rejected.path, rejected.reason);
+        }
+
+        let mut playlists = match saved.playlists.filter(|entries| !entries.is_empty()) {
+            Some(entries) => Playlists {
+                active: saved
+                    .active_playlist
+                    .filter(|name| entries.iter().any(|playlist| playlist.name == *name))
+                    .unwrap_or(entries[0].name.clone()),
+                entries,
+            },
+            None => Playlists {
+                active: DEFAULT_PLAYLIST.to_string(),
+                entries: vec![Playlist::new(
+                    DEFAULT_PLAYLIST.to_string(),
+                    true,
+                    vec![],
+                    self.interval,
+                    self.mode,
+                )],
+            },
+        };
+
+        playlists.for_each_inactive(|playlist| {
+            let queue = std::mem::take(&mut playlist.queue);
+            playlist.queue = sync_with_sources(queue, &found, playlist.follows_sources);
+        });
+
+        let queue = sync_with_sources(
+            saved.queue.unwrap_or_default(),
+            &found,
+            playlists.active().follows_sources,
+        );
+
+        let index = saved
+            .upcoming
+            .and_then(|upcoming| queue.iter().position(|bg| *bg == upcoming))
+            .unwrap_or(0);
+
+        Ok(WallpaperQueue::new(
+            Queue::new(
+                Some(queue),
+                saved.transitions.unwrap_or_default(),
+                saved.meta.unwrap_or_default(),
+            ),
+            index,
+            self.interval,
+            self.align,
+            Rotation::new(self.mode, self.seed),
+            Outputs::new(self.output_mode, saved.outputs.unwrap_or_default()),
+            self.backend,
+            self.transition,
+            self.sources,
+            images,
+            playlists,
+            History::new(saved.history.unwrap_or_default()),
+            self.state,
+            self.clock,
+        ))
+    }
+}
+
+/// Keep the saved order for wallpapers which still exist, dropping deleted ones and appending
+/// new ones in the order they were found.
+pub(super) fn reconcile(saved: Vec<String>, found: Vec<String>) -> Vec<String> {
+    let found_set: HashSet<&String> = found.iter().collect();
+    let mut queue: Vec<String> = saved
+        .into_iter()
+        .filter(|bg| found_set.contains(bg))
+        .collect();
+
+    let queued: HashSet<String> = queue.iter().cloned().collect();
+    queue.extend(found.into_iter().filter(|bg| !queued.contains(bg)));
+
+    queue
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wallpaper_queue::scheduler::tests::wallpapers;
+
+    use super::*;
+
+    #[test]
+    fn reconciles_the_saved_order_with_the_files_found() {
+        let saved = vec!["c".to_string(), "a".to_string(), "b".to_string()];
+        let found = vec!["a".to_string(), "b".to_string(), "d".to_string()];
+        assert_eq!(reconcile(saved, found), ["a", "b", "d"]);
+    }
+
+    #[tokio::test]
+    async fn restores_the_queue_without_wallpapers_deleted_while_stopped() {
+        let dir = wallpapers("restart", &["a.png", "b.png", "c.png"]);
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let state_path = dir.join("state").join("state.json");
+
+        let wq = WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .with_state_file(StateFile::load(&state_path))
+            .build()
+            .await
+            .unwrap();
+        wq.rearrange_wallpaper(&path("c.png"), &Position::Before, &path("a.png"))
+            .await
+            .unwrap();
+        wq.state.flush().await;
+        drop(wq);
+
+        // While the daemon is down, one wallpaper goes and another comes.
+        std::fs::remove_file(dir.join("a.png")).unwrap();
+        std::fs::copy(dir.join("b.png"), dir.join("d.png")).unwrap();
+
+        let wq = WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .with_state_file(StateFile::load(&state_path))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(
+            wq.get_queue().await,
+            [path("c.png"), path("b.png"), path("d.png")]
+        );
     }
 }
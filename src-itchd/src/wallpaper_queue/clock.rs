@@ -0,0 +1,252 @@
+//! Deadlines for the scheduler. They are kept both on [`tokio::time::Instant`], which tokio can
+//! pause in tests, and on the wall clock, which unlike it keeps running while the system is
+//! suspended. A deadline is due once either of them reaches it, so one which passed during a
+//! suspend is noticed on wake.
+
+use chrono::{DateTime, Days, FixedOffset, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone};
+use tokio::time::{Duration, Instant, sleep};
+
+/// Longest stretch slept in one go, and so the longest a deadline can be missed by after a
+/// suspend.
+const WAKE_CHECK: Duration = Duration::from_secs(10);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where the time of day comes from.
+#[derive(Clone, Debug, Default)]
+pub enum Clock {
+    /// The local time of the system.
+    #[default]
+    System,
+    /// Runs on tokio's clock from `start`, so it stands still while tokio's clock is paused,
+    /// plus however long [`Clock::suspend`] pretended the system was suspended.
+    #[cfg(test)]
+    Tokio {
+        start: DateTime<FixedOffset>,
+        since: Instant,
+        suspended: std::sync::Arc<std::sync::Mutex<Duration>>,
+    },
+}
+
+/// A point in time the scheduler waits for.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    instant: Instant,
+    wall: DateTime<FixedOffset>,
+}
+
+impl Clock {
+    #[cfg(test)]
+    pub fn starting_at(start: DateTime<FixedOffset>) -> Self {
+        Clock::Tokio {
+            start,
+            since: Instant::now(),
+            suspended: Default::default(),
+        }
+    }
+
+    /// Move the time of day on by `duration` without tokio's clock noticing, like a suspend.
+    #[cfg(test)]
+    pub fn suspend(&self, duration: Duration) {
+        if let Clock::Tokio { suspended, .. } = self {
+            *suspended.lock().unwrap() += duration;
+        }
+    }
+
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        match self {
+            Clock::System => Local::now().fixed_offset(),
+            #[cfg(test)]
+            Clock::Tokio {
+                start,
+                since,
+                suspended,
+            } => {
+                let elapsed = since.elapsed() + *suspended.lock().unwrap();
+                *start + TimeDelta::from_std(elapsed).unwrap_or_default()
+            }
+        }
+    }
+
+    pub fn after(&self, duration: Duration) -> Deadline {
+        Deadline {
+            instant: Instant::now() + duration,
+            wall: self.now() + TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX),
+        }
+    }
+
+    /// The deadline at which the wall clock, which shows `now` at the moment, shows `at`.
+    pub fn at<Tz: TimeZone>(&self, at: &DateTime<Tz>, now: &DateTime<Tz>) -> Deadline {
+        self.after(
+            at.clone()
+                .signed_duration_since(now)
+                .to_std()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Time left until `deadline`, zero once it passed on either clock.
+    pub fn remaining(&self, deadline: Deadline) -> Duration {
+        let wall = (deadline.wall - self.now()).to_std().unwrap_or_default();
+        deadline
+            .instant
+            .saturating_duration_since(Instant::now())
+            .min(wall)
+    }
+
+    /// Resolves once `deadline` passed, however long the system was suspended in between.
+    pub async fn sleep_until(&self, deadline: Deadline) {
+        loop {
+            let left = self.remaining(deadline);
+            if left.is_zero() {
+                return;
+            }
+            sleep(left.min(WAKE_CHECK)).await;
+        }
+    }
+
+    /// The deadline for the next multiple of `interval`, see [`next_aligned`].
+    pub fn next_aligned(&self, interval: Duration) -> Deadline {
+        match self {
+            // Local knows about daylight saving changes, a fixed offset does not.
+            Clock::System => {
+                let now = Local::now();
+                self.at(&next_aligned(&now, interval), &now)
+            }
+            #[cfg(test)]
+            Clock::Tokio { .. } => {
+                let now = self.now();
+                self.at(&next_aligned(&now, interval), &now)
+            }
+        }
+    }
+}
+
+/// The next multiple of `interval` after `now` counted from midnight, e.g. the top of the next
+/// hour for an hourly interval. Intervals which do not divide a day start over at midnight,
+/// intervals longer than a day are counted from midnight on 1970-01-01 so a two day interval
+/// switches at every other midnight.
+pub fn next_aligned<Tz: TimeZone>(now: &DateTime<Tz>, interval: Duration) -> DateTime<Tz> {
+    let local = now.naive_local();
+    let (start, end) = if interval > DAY {
+        (NaiveDate::default().and_time(NaiveTime::MIN), None)
+    } else {
+        let midnight = local.date().and_time(NaiveTime::MIN);
+        (midnight, midnight.checked_add_days(Days::new(1)))
+    };
+
+    let elapsed = (local - start).num_seconds().max(0) as u64;
+    let step = interval.as_secs().max(1);
+    let next = start + TimeDelta::seconds(((elapsed / step + 1) * step) as i64);
+    let next = match end {
+        Some(end) => next.min(end),
+        None => next,
+    };
+
+    // A time skipped by a daylight saving change is shown at the end of the gap.
+    now.timezone()
+        .from_local_datetime(&next)
+        .earliest()
+        .or_else(|| {
+            now.timezone()
+                .from_local_datetime(&(next + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .unwrap_or_else(|| now.clone() + TimeDelta::from_std(interval).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+
+    use super::*;
+
+    fn time(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn aligned(now: &str, interval: u64) -> DateTime<FixedOffset> {
+        next_aligned(&time(now), Duration::from_secs(interval))
+    }
+
+    #[test]
+    fn aligns_hourly_intervals_to_the_top_of_the_hour() {
+        assert_eq!(
+            aligned("2025-03-20T10:20:00+01:00", 3600),
+            time("2025-03-20T11:00:00+01:00")
+        );
+        assert_eq!(
+            aligned("2025-03-20T11:00:00+01:00", 3600),
+            time("2025-03-20T12:00:00+01:00")
+        );
+        assert_eq!(
+            aligned("2025-03-20T23:59:59+01:00", 3600),
+            time("2025-03-21T00:00:00+01:00")
+        );
+    }
+
+    #[test]
+    fn aligns_quarter_hours() {
+        assert_eq!(
+            aligned("2025-03-20T10:07:30+01:00", 15 * 60),
+            time("2025-03-20T10:15:00+01:00")
+        );
+        assert_eq!(
+            aligned("2025-03-20T10:45:00+01:00", 15 * 60),
+            time("2025-03-20T11:00:00+01:00")
+        );
+    }
+
+    #[test]
+    fn starts_over_at_midnight_for_intervals_which_do_not_divide_a_day() {
+        // 7h ticks at 07:00, 14:00 and 21:00, then midnight rather than 04:00.
+        assert_eq!(
+            aligned("2025-03-20T22:00:00+01:00", 7 * 3600),
+            time("2025-03-21T00:00:00+01:00")
+        );
+    }
+
+    #[test]
+    fn counts_intervals_longer_than_a_day_in_whole_days() {
+        // 2025-03-21 is day 20168 since 1970-01-01, so a two day interval ends on even days.
+        let two_days = 2 * 24 * 3600;
+        assert_eq!(
+            aligned("2025-03-20T10:00:00+01:00", two_days),
+            time("2025-03-21T00:00:00+01:00")
+        );
+        assert_eq!(
+            aligned("2025-03-21T00:00:00+01:00", two_days),
+            time("2025-03-23T00:00:00+01:00")
+        );
+        assert_eq!(
+            aligned("2025-03-22T10:00:00+01:00", two_days),
+            time("2025-03-23T00:00:00+01:00")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sleeps_until_the_deadline_on_tokio_time() {
+        let clock = Clock::starting_at(time("2025-03-20T10:00:00+01:00"));
+        let start = Instant::now();
+        clock
+            .sleep_until(clock.after(Duration::from_secs(3600)))
+            .await;
+        assert_eq!(start.elapsed(), Duration::from_secs(3600));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wakes_up_once_a_deadline_passed_during_a_suspend() {
+        let clock = Clock::starting_at(time("2025-03-20T10:00:00+01:00"));
+        let deadline = clock.after(Duration::from_secs(3600));
+        let start = Instant::now();
+
+        clock.suspend(Duration::from_secs(2 * 3600));
+        assert_eq!(clock.remaining(deadline), Duration::ZERO);
+        clock.sleep_until(deadline).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // Part way through, the rest is left.
+        let deadline = clock.after(Duration::from_secs(3600));
+        clock.suspend(Duration::from_secs(1000));
+        assert_eq!(clock.remaining(deadline), Duration::from_secs(2600));
+    }
+}
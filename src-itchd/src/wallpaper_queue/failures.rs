@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use swww_itch_shared::message::FailedWallpaper;
+
+use super::*;
+
+/// Failed attempts in a row after which the rotation passes over a wallpaper.
+pub const MAX_FAILURES: u32 = 3;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Wallpapers the backend failed to show, by path. A wallpaper is forgotten once it is shown
+/// or its file changes.
+#[derive(Default, Debug)]
+pub struct Failures {
+    by_wallpaper: BTreeMap<String, FailedWallpaper>,
+}
+
+impl Failures {
+    /// Count another failed attempt to show `bg`.
+    pub fn record(&mut self, bg: &str, error: String) -> FailedWallpaper {
+        let failed = self
+            .by_wallpaper
+            .entry(bg.to_string())
+            .or_insert_with(|| FailedWallpaper {
+                path: bg.to_string(),
+                failures: 0,
+                error: String::new(),
+                skipped: false,
+            });
+
+        failed.failures += 1;
+        failed.error = error;
+        failed.skipped = failed.failures >= MAX_FAILURES;
+        failed.clone()
+    }
+
+    pub fn forget(&mut self, bg: &str) {
+        self.by_wallpaper.remove(bg);
+    }
+
+    pub fn is_skipped(&self, bg: &str) -> bool {
+        self.by_wallpaper
+            .get(bg)
+            .is_some_and(|failed| failed.skipped)
+    }
+
+    pub fn list(&self) -> Vec<FailedWallpaper> {
+        self.by_wallpaper.values().cloned().collect()
+    }
+}
+
+/// Doubling delay between attempts to reach a backend which is not ready.
+#[derive(Debug)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self {
+            next: INITIAL_BACKOFF,
+        }
+    }
+
+    /// How long to wait before the next attempt.
+    pub fn next(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.next = INITIAL_BACKOFF;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_a_wallpaper_after_max_failures() {
+        let mut failures = Failures::default();
+
+        for attempt in 1..MAX_FAILURES {
+            let failed = failures.record("/bg/a.png", format!("exit {attempt}"));
+            assert_eq!(failed.failures, attempt);
+            assert!(!failed.skipped);
+            assert!(!failures.is_skipped("/bg/a.png"));
+        }
+
+        let failed = failures.record("/bg/a.png", "exit 1".to_string());
+        assert!(failed.skipped);
+        assert!(failures.is_skipped("/bg/a.png"));
+        assert!(!failures.is_skipped("/bg/b.png"));
+        assert_eq!(failures.list(), vec![failed]);
+
+        failures.forget("/bg/a.png");
+        assert!(!failures.is_skipped("/bg/a.png"));
+        assert!(failures.list().is_empty());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new();
+
+        let delays: Vec<u64> = (0..9).map(|_| backoff.next().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60, 60]);
+
+        backoff.reset();
+        assert_eq!(backoff.next(), INITIAL_BACKOFF);
+    }
+}
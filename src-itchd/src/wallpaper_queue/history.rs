@@ -0,0 +1,201 @@
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use swww_itch_shared::message::{HistoryEntry, SwitchReason};
+
+use super::*;
+
+/// How many shown wallpapers are remembered.
+const MAX_HISTORY: usize = 100;
+
+/// Wallpapers shown on the first output, oldest first.
+#[derive(Default, Debug)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    /// Entry the last [`Self::go_back`] returned, for as long as the wallpapers shown since came
+    /// from going back.
+    cursor: Option<usize>,
+}
+
+impl History {
+    pub fn new(entries: Vec<HistoryEntry>) -> Self {
+        let mut entries = VecDeque::from(entries);
+        while entries.len() > MAX_HISTORY {
+            entries.pop_front();
+        }
+        Self {
+            entries,
+            cursor: None,
+        }
+    }
+
+    /// Remember that `bg` is shown now, forgetting the oldest entry once the history is full.
+    /// Showing what [`Self::go_back`] returned keeps its place, anything else starts going back
+    /// from the newest entry again.
+    pub fn record(&mut self, bg: &str, reason: SwitchReason) {
+        let went_back = self
+            .cursor
+            .is_some_and(|cursor| self.entries[cursor].path == bg);
+        if !went_back {
+            self.cursor = None;
+        }
+
+        let shown_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.entries.push_back(HistoryEntry {
+            path: bg.to_string(),
+            shown_at,
+            reason,
+        });
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.pop_front();
+            // Once going back reached the oldest entry left, it stays there.
+            self.cursor = self.cursor.map(|cursor| cursor.saturating_sub(1));
+        }
+    }
+
+    /// The wallpaper shown before the newest one, or before the one going back returned last,
+    /// passing over those which are not `available`. The history itself is left alone.
+    pub fn go_back(&mut self, available: impl Fn(&str) -> bool) -> Option<String> {
+        let from = match self.cursor {
+            Some(cursor) => cursor,
+            None => self.entries.len().checked_sub(1)?,
+        };
+        let previous = (0..from)
+            .rev()
+            .find(|&i| available(&self.entries[i].path))?;
+
+        self.cursor = Some(previous);
+        Some(self.entries[previous].path.clone())
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<HistoryEntry> {
+        self.entries.iter().rev().cloned().collect()
+    }
+
+    pub fn save(&self, state: &StateFile) {
+        state.update(|state| state.history = Some(self.entries.iter().cloned().collect()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(shown: &[&str]) -> History {
+        let mut history = History::default();
+        for bg in shown {
+            history.record(bg, SwitchReason::Scheduled);
+        }
+        history
+    }
+
+    fn paths(history: &History) -> Vec<String> {
+        history
+            .entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// Go back and show what it returned, like the daemon does.
+    fn back(history: &mut History, available: impl Fn(&str) -> bool) -> Option<String> {
+        let bg = history.go_back(available)?;
+        history.record(&bg, SwitchReason::Manual);
+        Some(bg)
+    }
+
+    #[test]
+    fn goes_further_back_every_time() {
+        let mut history = recorded(&["a", "b", "c", "d"]);
+
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("c"));
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("b"));
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("a"));
+        assert_eq!(back(&mut history, |_| true), None);
+
+        // Every switch is kept, going back included.
+        assert_eq!(paths(&history), ["a", "b", "c", "d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn starts_over_from_the_newest_entry_after_another_switch() {
+        let mut history = recorded(&["a", "b", "c"]);
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("b"));
+
+        history.record("d", SwitchReason::Scheduled);
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("b"));
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn keeps_the_history_when_the_switch_does_not_happen() {
+        let mut history = recorded(&["a", "b", "c"]);
+
+        // The backend was not ready, so nothing was recorded.
+        assert_eq!(history.go_back(|_| true).as_deref(), Some("b"));
+        assert_eq!(paths(&history), ["a", "b", "c"]);
+        assert_eq!(history.list()[0].path, "c");
+    }
+
+    #[test]
+    fn passes_over_unavailable_wallpapers() {
+        let mut history = recorded(&["a", "b", "c", "d"]);
+        let available = |bg: &str| bg != "c" && bg != "b";
+
+        assert_eq!(back(&mut history, available).as_deref(), Some("a"));
+        assert_eq!(back(&mut history, available), None);
+        assert_eq!(history.go_back(|bg| bg == "z"), None);
+        assert_eq!(History::default().go_back(|_| true), None);
+        assert_eq!(recorded(&["a"]).go_back(|_| true), None);
+    }
+
+    #[test]
+    fn forgets_the_oldest_entries_beyond_the_limit() {
+        let shown: Vec<String> = (0..MAX_HISTORY + 20).map(|i| i.to_string()).collect();
+        let mut history = History::default();
+        for bg in &shown {
+            history.record(bg, SwitchReason::Scheduled);
+        }
+        assert_eq!(paths(&history), shown[20..]);
+
+        let restored = History::new(
+            shown
+                .iter()
+                .map(|bg| HistoryEntry {
+                    path: bg.clone(),
+                    shown_at: 0,
+                    reason: SwitchReason::Scheduled,
+                })
+                .collect(),
+        );
+        assert_eq!(paths(&restored), shown[20..]);
+
+        // Going back while the oldest entries are dropped keeps to the same wallpapers.
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("118"));
+        assert_eq!(back(&mut history, |_| true).as_deref(), Some("117"));
+        assert_eq!(history.entries.len(), MAX_HISTORY);
+        assert_eq!(history.entries[0].path, "22");
+    }
+
+    #[test]
+    fn goes_back_no_further_than_the_oldest_entry_left() {
+        let mut history = History::default();
+        for i in 0..MAX_HISTORY {
+            history.record(&i.to_string(), SwitchReason::Scheduled);
+        }
+
+        let mut went_back = 0;
+        while back(&mut history, |_| true).is_some() {
+            went_back += 1;
+        }
+        // Each step back pushes one of the oldest entries out of the history.
+        assert_eq!(went_back, MAX_HISTORY / 2);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashMap};
+
+use swww_itch_shared::message::OutputMode;
+
+use super::*;
+
+/// Where every output is in the queue when they rotate independently.
+pub struct Outputs {
+    pub mode: OutputMode,
+    queues: HashMap<String, OutputQueue>,
+    /// Upcoming wallpaper of outputs which were saved but not seen since the daemon started.
+    saved: BTreeMap<String, String>,
+    /// How many outputs the queue was last spread over.
+    pub spanned: usize,
+}
+
+/// Position of a single output when outputs rotate independently.
+pub struct OutputQueue {
+    /// Wallpaper to show next. Tracked by path so that it survives changes to the queue.
+    pub upcoming: Option<String>,
+    pub rotation: Rotation,
+}
+
+impl Outputs {
+    pub fn new(mode: OutputMode, saved: BTreeMap<String, String>) -> Self {
+        Self {
+            mode,
+            queues: HashMap::new(),
+            saved,
+            spanned: 1,
+        }
+    }
+
+    /// The position of `name`, which continues from where it was saved or otherwise starts
+    /// `offset` wallpapers after `primary`.
+    pub fn get_or_insert(
+        &mut self,
+        name: &str,
+        queue: &[String],
+        primary: usize,
+        offset: usize,
+        rotation: &mut Rotation,
+    ) -> &mut OutputQueue {
+        self.queues.entry(name.to_string()).or_insert_with(|| {
+            let upcoming = self
+                .saved
+                .remove(name)
+                .or_else(|| queue.get((primary + offset) % queue.len().max(1)).cloned());
+            OutputQueue {
+                upcoming,
+                rotation: rotation.sibling(),
+            }
+        })
+    }
+
+    pub fn queues_mut(&mut self) -> impl Iterator<Item = &mut OutputQueue> {
+        self.queues.values_mut()
+    }
+
+    /// Persist the upcoming wallpaper of every output, including ones which are unplugged.
+    pub fn save(&self, state: &StateFile) {
+        let mut upcoming = self.saved.clone();
+        upcoming.extend(self.queues.iter().filter_map(|(name, queue)| {
+            queue
+                .upcoming
+                .clone()
+                .map(|upcoming| (name.clone(), upcoming))
+        }));
+
+        state.update(|state| state.outputs = Some(upcoming));
+    }
+}
+
+impl OutputQueue {
+    /// Index of the upcoming wallpaper, or `fallback` if it is no longer queued.
+    pub fn index(&self, queue: &[String], fallback: usize) -> usize {
+        self.upcoming
+            .as_ref()
+            .and_then(|upcoming| queue.iter().position(|v| v == upcoming))
+            .unwrap_or(fallback)
+    }
+}
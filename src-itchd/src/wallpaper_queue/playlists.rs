@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use swww_itch_shared::{
+    message::{PlaylistInfo, RotationMode},
+    sun::SolarPhase,
+};
+
+use super::*;
+
+pub const DEFAULT_PLAYLIST: &str = "default";
+
+/// A named wallpaper order with its own interval and rotation mode.
+///
+/// The active playlist is played from the queue, interval and rotation of the
+/// [`WallpaperQueue`]. Its entry here is only brought up to date when another playlist is
+/// activated.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Playlist {
+    pub name: String,
+    /// Whether wallpapers which appear in the sources are appended to the playlist.
+    pub follows_sources: bool,
+    pub queue: Vec<String>,
+    /// The wallpaper to show next when the playlist is activated again.
+    pub upcoming: Option<String>,
+    /// Rotation interval in seconds.
+    pub interval: u64,
+    pub mode: RotationMode,
+    /// Phase of the sun the playlist is activated in.
+    #[serde(default)]
+    pub phase: Option<SolarPhase>,
+}
+
+impl Playlist {
+    pub fn new(
+        name: String,
+        follows_sources: bool,
+        queue: Vec<String>,
+        interval: Duration,
+        mode: RotationMode,
+    ) -> Self {
+        Self {
+            name,
+            follows_sources,
+            queue,
+            upcoming: None,
+            interval: interval.as_secs(),
+            mode,
+            phase: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Playlists {
+    pub active: String,
+    pub entries: Vec<Playlist>,
+}
+
+impl Playlists {
+    pub fn get(&self, name: &str) -> ItchResult<&Playlist> {
+        self.entries
+            .iter()
+            .find(|playlist| playlist.name == name)
+            .ok_or_else(|| ItchError::PlaylistNotFound(name.to_string()))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> ItchResult<&mut Playlist> {
+        self.entries
+            .iter_mut()
+            .find(|playlist| playlist.name == name)
+            .ok_or_else(|| ItchError::PlaylistNotFound(name.to_string()))
+    }
+
+    pub fn active(&self) -> &Playlist {
+        self.get(&self.active)
+            .expect("The active playlist should always exist")
+    }
+
+    /// Fails if `name` is empty or taken.
+    pub fn check_name(&self, name: &str) -> ItchResult<()> {
+        if name.trim().is_empty() {
+            return Err(ItchError::InvalidPlaylistName(name.to_string()));
+        }
+        if self.get(name).is_ok() {
+            return Err(ItchError::PlaylistExists(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// The first playlist tagged with `phase`.
+    pub fn tagged(&self, phase: SolarPhase) -> Option<&Playlist> {
+        self.entries
+            .iter()
+            .find(|playlist| playlist.phase == Some(phase))
+    }
+
+    /// Apply `f` to every playlist but the active one.
+    pub fn for_each_inactive(&mut self, f: impl FnMut(&mut Playlist)) {
+        let active = self.active.clone();
+        self.entries
+            .iter_mut()
+            .filter(|playlist| playlist.name != active)
+            .for_each(f);
+    }
+
+    pub fn info(
+        &self,
+        active_len: usize,
+        interval: Duration,
+        mode: RotationMode,
+    ) -> Vec<PlaylistInfo> {
+        self.entries
+            .iter()
+            .map(|playlist| {
+                let active = playlist.name == self.active;
+                PlaylistInfo {
+                    name: playlist.name.clone(),
+                    active,
+                    follows_sources: playlist.follows_sources,
+                    length: if active {
+                        active_len
+                    } else {
+                        playlist.queue.len()
+                    },
+                    interval: if active {
+                        interval.as_secs()
+                    } else {
+                        playlist.interval
+                    },
+                    mode: if active { mode } else { playlist.mode },
+                    phase: playlist.phase,
+                }
+            })
+            .collect()
+    }
+
+    /// Persist the playlists besides the active one's live order, interval and mode, which are
+    /// saved by the queue and scheduler.
+    pub fn save(&self, state: &StateFile) {
+        state.update(|state| {
+            state.active_playlist = Some(self.active.clone());
+            state.playlists = Some(self.entries.clone());
+        });
+    }
+}
+
+/// Drop the wallpapers which no longer exist from `queue`. If it follows the sources, new ones
+/// are appended in the order they were found.
+pub fn sync_with_sources(
+    queue: Vec<String>,
+    found: &[String],
+    follows_sources: bool,
+) -> Vec<String> {
+    if follows_sources {
+        return builder::reconcile(queue, found.to_vec());
+    }
+
+    let found: HashSet<&String> = found.iter().collect();
+    queue.into_iter().filter(|bg| found.contains(bg)).collect()
+}
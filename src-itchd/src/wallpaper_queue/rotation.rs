@@ -0,0 +1,408 @@
+use std::collections::VecDeque;
+
+use rand::{
+    Rng, SeedableRng,
+    rngs::StdRng,
+    seq::{IndexedRandom, SliceRandom},
+};
+use swww_itch_shared::message::RotationMode;
+
+use super::*;
+
+/// How many of the most recently shown wallpapers random mode avoids. Capped at half the queue
+/// so that small queues still get a random order rather than a fixed cycle.
+const RECENT_WINDOW: usize = 8;
+
+/// Picks the upcoming wallpaper according to the [`RotationMode`].
+pub struct Rotation {
+    mode: Arc<Mutex<RotationMode>>,
+    rng: StdRng,
+    /// Wallpapers not yet shown in the current shuffle cycle, drawn from the back.
+    bag: Vec<String>,
+    /// Most recently shown wallpapers, newest last.
+    history: VecDeque<String>,
+}
+
+impl Rotation {
+    /// A fixed `seed` makes the shuffle and random orders reproducible.
+    pub fn new(mode: RotationMode, seed: Option<u64>) -> Self {
+        Self {
+            mode: Arc::new(Mutex::new(mode)),
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_os_rng(),
+            },
+            bag: vec![],
+            history: VecDeque::new(),
+        }
+    }
+
+    /// A rotation with the same mode for another output, seeded from this one so that a fixed
+    /// seed stays reproducible.
+    pub fn sibling(&mut self) -> Self {
+        Self {
+            mode: self.mode.clone(),
+            rng: StdRng::from_rng(&mut self.rng),
+            bag: vec![],
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn mode(&self) -> Arc<Mutex<RotationMode>> {
+        self.mode.clone()
+    }
+
+    /// Switch modes, starting a fresh shuffle cycle.
+    pub async fn set_mode(&mut self, mode: RotationMode) {
+        *self.mode.lock().await = mode;
+        self.bag.clear();
+    }
+
+    /// The wallpaper shown last, if any was shown since the daemon started.
+    pub fn last_shown(&self) -> Option<&String> {
+        self.history.back()
+    }
+
+    /// Index of the wallpaper to show after the one at `applied`.
+    pub async fn advance(&mut self, queue: &Queue, applied: usize) -> usize {
+        let len = queue.v.len();
+        if len == 0 {
+            return 0;
+        }
+
+        if let Some(bg) = queue.v.get(applied) {
+            self.history.push_back(bg.clone());
+            if self.history.len() > RECENT_WINDOW.max(2) {
+                self.history.pop_front();
+            }
+        }
+
+        self.pick(queue, applied).await
+    }
+
+    /// Like [`Self::advance`], but passes over wallpapers for which `skip` is true unless
+    /// every wallpaper is skipped.
+    pub async fn advance_past(
+        &mut self,
+        queue: &Queue,
+        applied: usize,
+        skip: impl Fn(&str) -> bool,
+    ) -> usize {
+        let first = self.advance(queue, applied).await;
+        self.pick_past(queue, first, skip).await
+    }
+
+    /// `upcoming`, or if `skip` is true for it the first wallpaper after it for which it is not,
+    /// without recording anything as shown. `upcoming` if every wallpaper is skipped.
+    pub async fn pick_past(
+        &mut self,
+        queue: &Queue,
+        upcoming: usize,
+        skip: impl Fn(&str) -> bool,
+    ) -> usize {
+        let mut next = upcoming;
+        for _ in 0..queue.v.len() {
+            if !skip(&queue.v[next]) {
+                return next;
+            }
+            next = self.pick(queue, next).await;
+        }
+        upcoming
+    }
+
+    /// Index of the upcoming wallpaper given that `current` was shown last, without recording
+    /// it as shown again.
+    pub async fn pick(&mut self, queue: &Queue, current: usize) -> usize {
+        let len = queue.v.len();
+        if len == 0 {
+            return 0;
+        }
+
+        let mode = *self.mode.lock().await;
+        match mode {
+            RotationMode::Sequential => (current + 1) % len,
+            RotationMode::Reverse => (current + len - 1) % len,
+            RotationMode::Shuffle => self.draw_from_bag(queue, current),
+            RotationMode::Random => self.pick_random(queue, current),
+        }
+    }
+
+    /// Index to show so that the wallpaper before `upcoming` is shown again.
+    pub async fn previous(&mut self, queue: &Queue, upcoming: usize) -> usize {
+        let len = queue.v.len();
+
+        let mode = *self.mode.lock().await;
+        match mode {
+            // upcoming is one step ahead of the current wallpaper, so the previous one is two
+            // steps behind it.
+            RotationMode::Sequential => (upcoming + 2 * len - 2) % len,
+            RotationMode::Reverse => (upcoming + 2) % len,
+            RotationMode::Shuffle | RotationMode::Random => {
+                // Drop the current wallpaper from the history. The previous one is pushed again
+                // once it is shown.
+                self.history.pop_back();
+                self.history
+                    .pop_back()
+                    .and_then(|bg| queue.v.iter().position(|v| *v == bg))
+                    .unwrap_or(upcoming)
+            }
+        }
+    }
+
+    fn draw_from_bag(&mut self, queue: &Queue, current: usize) -> usize {
+        let current_bg = queue.v.get(current);
+
+        // Wallpapers removed from the queue mid-cycle are skipped, ones added mid-cycle wait
+        // for the next cycle.
+        self.bag
+            .retain(|bg| Some(bg) != current_bg && queue.v.contains(bg));
+
+        if self.bag.is_empty() {
+            self.bag = queue.v.to_vec();
+            self.bag.shuffle(&mut self.rng);
+            self.sort_by_weight(queue);
+
+            // Don't start the new cycle with the wallpaper which ended the last one.
+            let last = self.bag.len() - 1;
+            if last > 0 && self.bag.last() == current_bg {
+                let swap_with = self.rng.random_range(0..last);
+                self.bag.swap(swap_with, last);
+            }
+        }
+
+        self.bag
+            .pop()
+            .and_then(|bg| queue.v.iter().position(|v| *v == bg))
+            .unwrap_or(0)
+    }
+
+    /// Order the bag so that heavier wallpapers tend to be drawn earlier, leaving a bag in which
+    /// every wallpaper weighs the same in its shuffled order.
+    fn sort_by_weight(&mut self, queue: &Queue) {
+        if self.bag.iter().all(|bg| queue.weight(bg) == 1) {
+            return;
+        }
+
+        // Weighted random sampling after Efraimidis and Spirakis. The bag is drawn from the
+        // back, so the largest keys go last.
+        let mut keyed: Vec<(f64, String)> = self
+            .bag
+            .drain(..)
+            .map(|bg| {
+                let key = self
+                    .rng
+                    .random::<f64>()
+                    .powf(1.0 / queue.weight(&bg) as f64);
+                (key, bg)
+            })
+            .collect();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.bag = keyed.into_iter().map(|(_, bg)| bg).collect();
+    }
+
+    fn pick_random(&mut self, queue: &Queue, current: usize) -> usize {
+        let len = queue.v.len();
+        let window = RECENT_WINDOW.min(len / 2);
+        let recent: Vec<&String> = self.history.iter().rev().take(window).collect();
+
+        let eligible: Vec<usize> = (0..len)
+            .filter(|&i| i != current || len == 1)
+            .filter(|&i| !recent.contains(&&queue.v[i]))
+            .collect();
+
+        match eligible.is_empty() {
+            true => self.rng.random_range(0..len),
+            false => *eligible
+                .choose_weighted(&mut self.rng, |&i| queue.weight(&queue.v[i]))
+                .expect("Weights should be positive"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(len: usize) -> Queue {
+        let v = (0..len).map(|i| format!("/bg/{i}.png")).collect();
+        Queue::new(Some(v), BTreeMap::new(), BTreeMap::new())
+    }
+
+    /// A queue of `len` wallpapers in which the last one weighs `weight`.
+    fn weighted(len: usize, weight: u32) -> Queue {
+        let v: Vec<String> = (0..len).map(|i| format!("/bg/{i}.png")).collect();
+        let meta = WallpaperMeta {
+            weight: Some(weight),
+            ..Default::default()
+        };
+        let meta = BTreeMap::from([(v[len - 1].clone(), meta)]);
+        Queue::new(Some(v), BTreeMap::new(), meta)
+    }
+
+    /// Indices shown by `steps` switches starting from the wallpaper at 0.
+    async fn play(rotation: &mut Rotation, queue: &Queue, steps: usize) -> Vec<usize> {
+        let mut shown = 0;
+        let mut played = vec![];
+        for _ in 0..steps {
+            shown = rotation.advance(queue, shown).await;
+            played.push(shown);
+        }
+        played
+    }
+
+    #[tokio::test]
+    async fn shuffle_shows_every_wallpaper_once_per_cycle() {
+        let queue = queue(5);
+
+        for seed in 0..20 {
+            let mut rotation = Rotation::new(RotationMode::Shuffle, Some(seed));
+            let played = play(&mut rotation, &queue, 20).await;
+
+            for cycle in played.chunks(5) {
+                let mut sorted = cycle.to_vec();
+                sorted.sort();
+                assert_eq!(sorted, vec![0, 1, 2, 3, 4], "seed {seed}: {played:?}");
+            }
+            // Also across the boundary between two cycles.
+            assert!(
+                played.windows(2).all(|pair| pair[0] != pair[1]),
+                "seed {seed}: {played:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn shuffle_is_reproducible_with_a_seed() {
+        let queue = queue(8);
+
+        let mut first = Rotation::new(RotationMode::Shuffle, Some(7));
+        let mut second = Rotation::new(RotationMode::Shuffle, Some(7));
+        assert_eq!(
+            play(&mut first, &queue, 24).await,
+            play(&mut second, &queue, 24).await
+        );
+    }
+
+    #[tokio::test]
+    async fn random_avoids_recently_shown_wallpapers() {
+        let queue = queue(10);
+        // Half of the queue, as it is smaller than twice the window.
+        let window = 5;
+
+        for seed in 0..10 {
+            let mut rotation = Rotation::new(RotationMode::Random, Some(seed));
+            let mut played = vec![0];
+            played.extend(play(&mut rotation, &queue, 200).await);
+
+            for (i, shown) in played.iter().enumerate().skip(1) {
+                let recent = &played[i.saturating_sub(window)..i];
+                assert!(!recent.contains(shown), "seed {seed}: {played:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shuffle_draws_heavier_wallpapers_earlier_in_a_cycle() {
+        let queue = weighted(5, 10);
+
+        let mut positions = vec![];
+        for seed in 0..20 {
+            let mut rotation = Rotation::new(RotationMode::Shuffle, Some(seed));
+            let played = play(&mut rotation, &queue, 50).await;
+
+            for cycle in played.chunks(5) {
+                positions.extend(cycle.iter().position(|&shown| shown == 4));
+            }
+        }
+
+        // Evenly weighted it would come up third on average, and first a fifth of the time.
+        assert_eq!(positions.len(), 200);
+        let first = positions.iter().filter(|&&position| position == 0).count();
+        let mean = positions.iter().sum::<usize>() as f64 / positions.len() as f64;
+        assert!(first > 100, "first in {first} of 200 cycles");
+        assert!(mean < 1.0, "mean position {mean}");
+    }
+
+    #[tokio::test]
+    async fn random_picks_heavier_wallpapers_more_often() {
+        let queue = weighted(10, 10);
+
+        let mut counts = [0; 10];
+        for seed in 0..10 {
+            let mut rotation = Rotation::new(RotationMode::Random, Some(seed));
+            for shown in play(&mut rotation, &queue, 200).await {
+                counts[shown] += 1;
+            }
+        }
+
+        // Evenly weighted every wallpaper would come up about 200 times. The heavy one still
+        // sits out while it is recent, which keeps it from coming up ten times as often.
+        let lightest = *counts[..9].iter().max().unwrap();
+        assert!(counts[9] as f64 > 1.4 * lightest as f64, "{counts:?}");
+    }
+
+    #[tokio::test]
+    async fn advance_past_passes_over_skipped_wallpapers() {
+        let queue = queue(5);
+        let mut rotation = Rotation::new(RotationMode::Sequential, Some(0));
+
+        let skipped = |bg: &str| bg == "/bg/1.png" || bg == "/bg/2.png";
+        assert_eq!(rotation.advance_past(&queue, 0, skipped).await, 3);
+        assert_eq!(rotation.advance_past(&queue, 3, skipped).await, 4);
+        assert_eq!(rotation.advance_past(&queue, 4, skipped).await, 0);
+    }
+
+    #[tokio::test]
+    async fn pick_past_keeps_the_upcoming_wallpaper_unless_it_is_skipped() {
+        let queue = queue(4);
+        let mut rotation = Rotation::new(RotationMode::Sequential, Some(0));
+
+        let skipped = |bg: &str| bg == "/bg/1.png";
+        assert_eq!(rotation.pick_past(&queue, 0, skipped).await, 0);
+        assert_eq!(rotation.pick_past(&queue, 1, skipped).await, 2);
+        assert_eq!(rotation.pick_past(&queue, 1, |_| true).await, 1);
+        assert!(rotation.last_shown().is_none());
+    }
+
+    #[tokio::test]
+    async fn advance_past_falls_back_when_every_wallpaper_is_skipped() {
+        let queue = queue(3);
+
+        let mut rotation = Rotation::new(RotationMode::Sequential, Some(0));
+        assert_eq!(rotation.advance_past(&queue, 0, |_| true).await, 1);
+
+        // Shuffle still finishes its cycle instead of looping forever.
+        let mut rotation = Rotation::new(RotationMode::Shuffle, Some(0));
+        let next = rotation.advance_past(&queue, 0, |_| true).await;
+        assert!(next < 3);
+    }
+
+    #[tokio::test]
+    async fn reverse_walks_backwards() {
+        let queue = queue(4);
+        let mut rotation = Rotation::new(RotationMode::Reverse, Some(0));
+
+        assert_eq!(play(&mut rotation, &queue, 5).await, vec![3, 2, 1, 0, 3]);
+    }
+
+    #[tokio::test]
+    async fn previous_returns_the_wallpaper_before_the_current_one() {
+        let queue = queue(5);
+
+        // 2 is shown, so 3 is upcoming and 1 was shown before.
+        let mut rotation = Rotation::new(RotationMode::Sequential, Some(0));
+        assert_eq!(rotation.previous(&queue, 3).await, 1);
+        assert_eq!(rotation.previous(&queue, 1).await, 4);
+
+        let mut rotation = Rotation::new(RotationMode::Reverse, Some(0));
+        assert_eq!(rotation.previous(&queue, 1).await, 3);
+        assert_eq!(rotation.previous(&queue, 4).await, 1);
+
+        for mode in [RotationMode::Shuffle, RotationMode::Random] {
+            let mut rotation = Rotation::new(mode, Some(3));
+            let shown = rotation.advance(&queue, 0).await;
+            let upcoming = rotation.advance(&queue, shown).await;
+            assert_eq!(rotation.previous(&queue, upcoming).await, 0, "{mode}");
+        }
+    }
+}
@@ -1,25 +1,87 @@
-use std::ops::ControlFlow;
-
-use tokio::time::{Duration, Instant, sleep_until};
+use tokio::sync::oneshot;
 
 use super::*;
-use swww_itch_shared::swww_ffi;
+use swww_itch_shared::{
+    backend::{BackendError, WallpaperBackend},
+    message::{OutputMode, SwitchReason},
+    schedule::Cron,
+};
 
-#[derive(Clone)]
 pub enum Command {
     Interval(Duration),
-    Index(usize),
-    Shutdown,
+    /// Queue index to show, the output to show it on and why.
+    Index(usize, Option<String>, SwitchReason),
+    Next,
+    Previous,
+    Pause,
+    Resume,
+    Mode(RotationMode),
+    OutputMode(OutputMode),
+    /// The playlist to play, and who to tell once it is.
+    Playlist(Box<Playlist>, oneshot::Sender<()>),
+    Rule(Option<ActiveRule>),
+    /// How light it is outside now.
+    Phase(Option<SolarPhase>),
+    Status(oneshot::Sender<Snapshot>),
+}
+
+/// The schedule rule which applies right now.
+pub struct ActiveRule {
+    pub label: String,
+    /// Times to switch at in place of the interval.
+    pub at: Option<Cron>,
+}
+
+/// What the scheduler is showing and when it switches next.
+pub struct Snapshot {
+    pub shown: Option<String>,
+    pub rule: Option<String>,
+    pub output_mode: OutputMode,
+    /// How long the shown wallpaper stays up.
+    pub duration: Duration,
+    pub remaining: Duration,
+    pub paused: bool,
 }
 
 impl Scheduler {
-    pub fn start(queue: Arc<Mutex<Queue>>, current_index: Arc<Mutex<usize>>) -> SchedulerRemote {
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        queue: Arc<Mutex<Queue>>,
+        current_index: Arc<Mutex<usize>>,
+        interval: Arc<Mutex<Duration>>,
+        rotation: Rotation,
+        align: bool,
+        outputs: Outputs,
+        backend: Arc<dyn WallpaperBackend>,
+        failures: Arc<std::sync::Mutex<Failures>>,
+        history: Arc<std::sync::Mutex<History>>,
+        transition: TransitionOptions,
+        events: broadcast::Sender<Event>,
+        state: StateFile,
+        clock: Clock,
+    ) -> SchedulerRemote {
         let (command_tx, command_rx) = mpsc::channel(8);
         let scheduler = Scheduler {
             queue,
             command_rx,
-            interval: Duration::from_secs(60 * 60),
+            interval,
+            align,
+            clock,
+            rotation,
+            outputs,
+            backend,
+            failures,
+            history,
+            backoff: Backoff::new(),
+            transition,
             current_index,
+            events,
+            state,
+            paused: None,
+            shown: None,
+            requested: None,
+            rule: None,
+            phase: None,
         };
 
         tokio::spawn(scheduler.run());
@@ -28,23 +90,45 @@ impl Scheduler {
     }
 
     async fn run(mut self) {
-        let start = Instant::now();
-        let mut timeout = start + self.interval;
+        let mut timeout = self.next_switch(self.shown_interval().await);
         let (tx, mut end_timeout_rx) = mpsc::channel::<()>(1);
         let end_timeout = async move || tx.send(()).await;
 
-        let reset_timeout = |timeout: &mut Instant, to: &Duration| {
-            *timeout = Instant::now() + *to;
-        };
-
         loop {
+            let is_paused = self.paused.is_some();
+            let clock = self.clock.clone();
+            let sleep = async move {
+                if is_paused {
+                    std::future::pending().await
+                } else {
+                    clock.sleep_until(timeout).await
+                }
+            };
+
             tokio::select! {
-                Some(command) = self.command_rx.recv() => if self.handle_command(command, &end_timeout).await.is_break() {
-                    break;
-                },
-                _ = Self::first(sleep_until(timeout), end_timeout_rx.recv()) => {
-                    self.do_interval_task().await;
-                    reset_timeout(&mut timeout, &self.interval);
+                Some(command) = self.command_rx.recv() => self.handle_command(command, &mut timeout, &end_timeout).await,
+                (scheduled, _) = Self::first(sleep, end_timeout_rx.recv()) => match self.do_interval_task(self.switch_reason(scheduled.is_some())).await {
+                    Ok(()) => {
+                        self.backoff.reset();
+                        self.requested = None;
+
+                        // Aligned and exact switch times stay put after a manual step.
+                        timeout = self.next_switch(self.shown_interval().await);
+
+                        // A manual step while paused restarts the frozen countdown.
+                        if self.paused.is_some() {
+                            self.paused = Some(self.clock.remaining(timeout));
+                        }
+                    }
+                    Err(err) => {
+                        // Try the same wallpaper again once the backend had time to come up.
+                        let retry = self.backoff.next();
+                        eprintln!(
+                            "Wallpaper backend is not ready, retrying in {}: {err}",
+                            format_duration(retry)
+                        );
+                        timeout = self.clock.after(retry);
+                    }
                 },
             }
         }
@@ -63,53 +147,1173 @@ impl Scheduler {
     async fn handle_command<T, E>(
         &mut self,
         command: Command,
+        timeout: &mut Deadline,
         end_timeout: &impl AsyncFn() -> Result<T, E>,
-    ) -> ControlFlow<(), ()> {
+    ) {
         match command {
-            Command::Interval(interval) => self.interval = interval,
-            Command::Index(index) => {
+            Command::Interval(interval) => {
+                *self.interval.lock().await = interval;
+
+                // Restart the countdown so a shorter interval takes effect right away.
+                *timeout = self.next_switch(self.shown_interval().await);
+                if self.paused.is_some() {
+                    self.paused = Some(self.clock.remaining(*timeout));
+                }
+
+                let _ = self.events.send(Event::IntervalChanged(interval.as_secs()));
+            }
+            Command::Index(index, None, reason) => {
                 *self.current_index.lock().await = index;
+                self.requested = Some(reason);
                 let _ = end_timeout().await;
             }
-            Command::Shutdown => return ControlFlow::Break(()),
+            Command::Index(index, Some(output), reason) => {
+                self.switch_output(index, &output, reason).await
+            }
+            Command::Next => {
+                let _ = end_timeout().await;
+            }
+            Command::Previous => {
+                let queue = self.queue.lock().await;
+                if !queue.v.is_empty() {
+                    let mut index = self.current_index.lock().await;
+
+                    // A spanned switch moved as many wallpapers ahead as there were outputs.
+                    let steps = match self.outputs.mode {
+                        OutputMode::Spanned => self.outputs.spanned,
+                        _ => 1,
+                    };
+                    for _ in 0..steps {
+                        *index = self.rotation.previous(&queue, *index).await;
+                    }
+
+                    for output in self.outputs.queues_mut() {
+                        let upcoming = output.index(&queue.v, 0);
+                        let previous = output.rotation.previous(&queue, upcoming).await;
+                        output.upcoming = queue.v.get(previous).cloned();
+                    }
+                }
+                drop(queue);
+                // Going back shows the previous wallpaper even if it is meant for another phase.
+                self.requested = Some(SwitchReason::Manual);
+                let _ = end_timeout().await;
+            }
+            Command::Pause => {
+                if self.paused.is_none() {
+                    self.paused = Some(self.clock.remaining(*timeout));
+                    let _ = self.events.send(Event::PausedChanged(true));
+                }
+            }
+            Command::Resume => {
+                if let Some(remaining) = self.paused.take() {
+                    // Aligned and exact switch times which passed while paused are skipped.
+                    let interval = self.shown_interval().await;
+                    *timeout = self
+                        .fixed_switch(interval)
+                        .unwrap_or_else(|| self.clock.after(remaining));
+                    let _ = self.events.send(Event::PausedChanged(false));
+                }
+            }
+            Command::Mode(mode) => {
+                self.rotation.set_mode(mode).await;
+                for output in self.outputs.queues_mut() {
+                    output.rotation.set_mode(mode).await;
+                }
+
+                // Pick the upcoming wallpaper again so the new mode applies from the next switch.
+                let queue = self.queue.lock().await;
+                let current = self
+                    .rotation
+                    .last_shown()
+                    .and_then(|bg| queue.v.iter().position(|v| v == bg));
+                if let Some(current) = current {
+                    let mut index = self.current_index.lock().await;
+                    *index = self.rotation.pick(&queue, current).await;
+                    queue.save(&self.state, *index);
+                }
+
+                let _ = self.events.send(Event::ModeChanged(mode));
+            }
+            Command::OutputMode(mode) => {
+                // Takes effect on the next switch. Outputs without a position of their own in
+                // independent mode get one then.
+                self.outputs.mode = mode;
+            }
+            Command::Playlist(playlist, applied) => {
+                let interval = Duration::from_secs(playlist.interval);
+                if !interval.is_zero() && interval != *self.interval.lock().await {
+                    *self.interval.lock().await = interval;
+                    *timeout = self.next_switch(self.shown_interval().await);
+                    if self.paused.is_some() {
+                        self.paused = Some(self.clock.remaining(*timeout));
+                    }
+                    let _ = self.events.send(Event::IntervalChanged(interval.as_secs()));
+                }
+
+                if playlist.mode != *self.rotation.mode().lock().await {
+                    self.rotation.set_mode(playlist.mode).await;
+                    for output in self.outputs.queues_mut() {
+                        output.rotation.set_mode(playlist.mode).await;
+                    }
+                    let _ = self.events.send(Event::ModeChanged(playlist.mode));
+                }
+
+                let mut queue = self.queue.lock().await;
+                let mut index = self.current_index.lock().await;
+
+                queue.v = playlist.queue;
+                *index = playlist
+                    .upcoming
+                    .and_then(|upcoming| queue.v.iter().position(|v| *v == upcoming))
+                    .unwrap_or(0);
+
+                queue.save(&self.state, *index);
+                let _ = self.events.send(Event::QueueChanged(queue.v.clone()));
+
+                drop(index);
+                drop(queue);
+                let _ = applied.send(());
+            }
+            Command::Rule(rule) => {
+                let was_exact = self.next_exact_switch().is_some();
+                self.rule = rule;
+
+                // Leaving exact times goes back to the interval, counting from now.
+                if was_exact || self.next_exact_switch().is_some() {
+                    *timeout = self.next_switch(self.shown_interval().await);
+                    if self.paused.is_some() {
+                        self.paused = Some(self.clock.remaining(*timeout));
+                    }
+                }
+
+                let label = self.rule.as_ref().map(|rule| rule.label.clone());
+                let _ = self.events.send(Event::RuleChanged(label));
+            }
+            Command::Phase(phase) => {
+                self.phase = phase;
+
+                // Wallpapers meant for the phase which just ended make way right away.
+                let queue = self.queue.lock().await;
+                let misfit = self.shown.as_ref().is_some_and(|bg| !queue.fits(bg, phase));
+                drop(queue);
+                if misfit && self.paused.is_none() {
+                    *timeout = self.clock.after(Duration::ZERO);
+                }
+            }
+            Command::Status(tx) => {
+                let _ = tx.send(Snapshot {
+                    shown: self.shown.clone(),
+                    rule: self.rule.as_ref().map(|rule| rule.label.clone()),
+                    output_mode: self.outputs.mode,
+                    duration: self.shown_interval().await,
+                    remaining: self
+                        .paused
+                        .unwrap_or_else(|| self.clock.remaining(*timeout)),
+                    paused: self.paused.is_some(),
+                });
+            }
         }
-        ControlFlow::Continue(())
     }
 
-    async fn do_interval_task(&self) {
+    /// Show the upcoming wallpaper and pick the next one. Fails without moving on if the backend
+    /// is not ready or goes away while showing it. A pinned wallpaper is only switched away from
+    /// by hand.
+    async fn do_interval_task(&mut self, reason: SwitchReason) -> Result<(), BackendError> {
+        let scheduled = matches!(reason, SwitchReason::Scheduled | SwitchReason::Rule);
+        {
+            let queue = self.queue.lock().await;
+            if queue.v.is_empty() {
+                return Ok(());
+            }
+            if scheduled && self.shown.as_ref().is_some_and(|bg| queue.is_pinned(bg)) {
+                return Ok(());
+            }
+        }
+
+        // Ask the backend before taking any lock, as it may take a while to answer.
+        self.backend.ready().await?;
+        let outputs = match self.outputs.mode {
+            OutputMode::Same => vec![],
+            OutputMode::Independent | OutputMode::Spanned => self.query_outputs().await,
+        };
+
         let queue = self.queue.lock().await;
+        if queue.v.is_empty() {
+            return Ok(());
+        }
 
+        let skip = skipped(&self.failures, &queue, self.phase);
         let mut index = self.current_index.lock().await;
+        let mut applied = (*index).min(queue.v.len() - 1);
 
-        let maybe_wallpaper = {
-            if let Some(wallpaper) = queue.v.get(*index) {
-                Some(wallpaper)
-            } else if let Some(wallpaper) = queue.v.last() {
-                Some(wallpaper)
-            } else {
-                None
+        // The upcoming wallpaper may have been picked before the phase changed, unless it was
+        // asked for.
+        let repick = self.requested.is_none();
+        if repick {
+            applied = self.rotation.pick_past(&queue, applied, &skip).await;
+        }
+
+        match self.outputs.mode {
+            OutputMode::Spanned if !outputs.is_empty() => {
+                let mut current = applied;
+                let mut shown = false;
+                for (i, output) in outputs.iter().enumerate() {
+                    if i > 0 {
+                        current = self.rotation.advance_past(&queue, current, &skip).await;
+                    }
+                    let ok = self.apply(&queue, current, Some(output)).await?;
+                    shown |= ok && i == 0;
+                }
+                // Recorded once every output is done, as the whole switch is retried otherwise.
+                if shown {
+                    self.shown = Some(queue.v[applied].clone());
+                    self.record(&queue.v[applied], reason);
+                }
+                *index = self.rotation.advance_past(&queue, current, &skip).await;
+                self.outputs.spanned = outputs.len();
+            }
+            OutputMode::Independent if !outputs.is_empty() => {
+                // The first output plays the queue itself, the others follow their own position.
+                let shown = self.apply(&queue, applied, Some(&outputs[0])).await?;
+
+                for (offset, name) in outputs.iter().enumerate().skip(1) {
+                    let output = self.outputs.get_or_insert(
+                        name,
+                        &queue.v,
+                        applied,
+                        offset,
+                        &mut self.rotation,
+                    );
+                    let mut current = output.index(&queue.v, (applied + offset) % queue.v.len());
+                    if repick {
+                        current = output.rotation.pick_past(&queue, current, &skip).await;
+                    }
+                    self.apply(&queue, current, Some(name)).await?;
+
+                    let output = self.outputs.get_or_insert(
+                        name,
+                        &queue.v,
+                        applied,
+                        offset,
+                        &mut self.rotation,
+                    );
+                    let next = output.rotation.advance_past(&queue, current, &skip).await;
+                    output.upcoming = queue.v.get(next).cloned();
+                }
+                self.outputs.save(&self.state);
+
+                if shown {
+                    self.shown = Some(queue.v[applied].clone());
+                    self.record(&queue.v[applied], reason);
+                }
+                *index = self.rotation.advance_past(&queue, applied, &skip).await;
+            }
+            // Without known outputs, fall back to showing the same wallpaper everywhere.
+            _ => {
+                if self.apply(&queue, applied, None).await? {
+                    self.shown = Some(queue.v[applied].clone());
+                    self.record(&queue.v[applied], reason);
+                }
+                *index = self.rotation.advance_past(&queue, applied, &skip).await;
             }
+        }
+
+        queue.save(&self.state, *index);
+        Ok(())
+    }
+
+    /// Show the wallpaper at `index` on a single output right away. With independent outputs
+    /// that output carries on from there, otherwise the rotation is left alone.
+    async fn switch_output(&mut self, index: usize, name: &str, reason: SwitchReason) {
+        let queue = self.queue.lock().await;
+        if index >= queue.v.len() {
+            return;
+        }
+
+        // Nothing is retried here, as the wallpaper was asked for by hand.
+        let Ok(shown) = self.apply(&queue, index, Some(name)).await else {
+            return;
         };
 
-        if let Some(wallpaper) = maybe_wallpaper {
-            println!("[wq::scheduler.rs] {wallpaper}");
+        if self.outputs.mode != OutputMode::Independent {
+            return;
+        }
 
-            swww_ffi::set_background(wallpaper).await;
+        let outputs = self.query_outputs().await;
+        match outputs.iter().position(|output| output == name) {
+            Some(0) | None => {
+                if shown {
+                    self.shown = Some(queue.v[index].clone());
+                    self.record(&queue.v[index], reason);
+                }
+                let mut current_index = self.current_index.lock().await;
+                let skip = skipped(&self.failures, &queue, self.phase);
+                *current_index = self.rotation.advance_past(&queue, index, skip).await;
+                queue.save(&self.state, *current_index);
+            }
+            Some(offset) => {
+                let primary = *self.current_index.lock().await;
+                let output =
+                    self.outputs
+                        .get_or_insert(name, &queue.v, primary, offset, &mut self.rotation);
+                let skip = skipped(&self.failures, &queue, self.phase);
+                let next = output.rotation.advance_past(&queue, index, skip).await;
+                output.upcoming = queue.v.get(next).cloned();
+                self.outputs.save(&self.state);
+            }
+        }
+    }
+
+    /// Show the wallpaper at `index` with its own transition, or the default one. Returns
+    /// whether the backend showed it, or fails if the backend is gone and it should be tried
+    /// again later.
+    async fn apply(
+        &self,
+        queue: &Queue,
+        index: usize,
+        output: Option<&str>,
+    ) -> Result<bool, BackendError> {
+        let wallpaper = &queue.v[index];
+        let transition = queue.transition(wallpaper, &self.transition);
+
+        match output {
+            Some(output) => println!("[wq::scheduler.rs] {wallpaper} on {output}"),
+            None => println!("[wq::scheduler.rs] {wallpaper}"),
+        }
+
+        let outputs: Vec<String> = output.into_iter().map(str::to_string).collect();
+        match self
+            .backend
+            .set_wallpaper(wallpaper, &outputs, transition)
+            .await
+        {
+            Ok(()) => {
+                self.failures.lock().unwrap().forget(wallpaper);
+                let _ = self.events.send(Event::WallpaperChanged((
+                    wallpaper.to_owned(),
+                    index,
+                    output.map(str::to_string),
+                )));
+                Ok(true)
+            }
+            Err(BackendError::Unavailable(err)) => Err(BackendError::Unavailable(format!(
+                "Failed to show {wallpaper}: {err}"
+            ))),
+            Err(BackendError::Failed(err)) => {
+                eprintln!("Failed to show {wallpaper}: {err}");
+
+                let failed = self.failures.lock().unwrap().record(wallpaper, err);
+                if failed.failures == MAX_FAILURES {
+                    eprintln!(
+                        "Skipping {wallpaper} after {} failed attempts",
+                        failed.failures
+                    );
+                }
+                let _ = self.events.send(Event::WallpaperFailed(failed));
+                Ok(false)
+            }
         }
+    }
+
+    /// Add a wallpaper shown on the first output to the history.
+    fn record(&self, bg: &str, reason: SwitchReason) {
+        let mut history = self.history.lock().unwrap();
+        history.record(bg, reason);
+        history.save(&self.state);
+    }
 
-        if *index >= queue.v.len() - 1 {
-            *index = 0;
-        } else {
-            *index += 1;
+    /// Why the switch which is due now happens. A switch which was asked for keeps its reason
+    /// while the backend is retried.
+    fn switch_reason(&self, scheduled: bool) -> SwitchReason {
+        match self.requested {
+            Some(reason) => reason,
+            None if !scheduled => SwitchReason::Manual,
+            None if self.next_exact_switch().is_some() => SwitchReason::Rule,
+            None => SwitchReason::Scheduled,
         }
     }
+
+    /// How long the wallpaper shown last stays up: its own duration, or the interval.
+    async fn shown_interval(&self) -> Duration {
+        let duration = match &self.shown {
+            Some(bg) => self.queue.lock().await.duration(bg),
+            None => None,
+        };
+
+        match duration {
+            Some(duration) => duration,
+            None => *self.interval.lock().await,
+        }
+    }
+
+    /// When the next switch is due if the countdown starts now.
+    fn next_switch(&self, interval: Duration) -> Deadline {
+        self.fixed_switch(interval)
+            .unwrap_or_else(|| self.clock.after(interval))
+    }
+
+    /// When the next switch is due if that does not depend on when the countdown started: at the
+    /// times of the active schedule rule, or at the next multiple of the interval when aligned.
+    fn fixed_switch(&self, interval: Duration) -> Option<Deadline> {
+        self.next_exact_switch()
+            .or_else(|| self.align.then(|| self.clock.next_aligned(interval)))
+    }
+
+    /// When the active schedule rule wants the next switch, if it gives exact times.
+    fn next_exact_switch(&self) -> Option<Deadline> {
+        let at = self.rule.as_ref()?.at.as_ref()?;
+
+        let now = self.clock.now().naive_local();
+        let next = at.next_after(now)?;
+        Some(self.clock.after((next - now).to_std().unwrap_or_default()))
+    }
+
+    async fn query_outputs(&self) -> Vec<String> {
+        self.backend
+            .outputs()
+            .await
+            .map(|outputs| outputs.into_iter().map(|output| output.name).collect())
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to query outputs: {err}");
+                vec![]
+            })
+    }
+}
+
+/// Whether the rotation passes over a wallpaper because it failed too often, or because it is
+/// meant for another phase than `phase`.
+fn skipped<'a>(
+    failures: &'a std::sync::Mutex<Failures>,
+    queue: &'a Queue,
+    phase: Option<SolarPhase>,
+) -> impl Fn(&str) -> bool + 'a {
+    move |bg| failures.lock().unwrap().is_skipped(bg) || !queue.fits(bg, phase)
 }
 
 impl SchedulerRemote {
     pub async fn reset_timeout_and_set_index(
         &self,
         index: usize,
+        reason: SwitchReason,
     ) -> Result<(), mpsc::error::SendError<Command>> {
-        self.command_tx.send(Command::Index(index)).await
+        self.command_tx
+            .send(Command::Index(index, None, reason))
+            .await
+    }
+
+    /// Show the wallpaper at `index` on `output` without restarting the countdown.
+    pub async fn switch_output(
+        &self,
+        index: usize,
+        output: String,
+        reason: SwitchReason,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx
+            .send(Command::Index(index, Some(output), reason))
+            .await
+    }
+
+    pub async fn set_interval(
+        &self,
+        interval: Duration,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Interval(interval)).await
+    }
+
+    /// Apply the upcoming wallpaper now and restart the countdown.
+    pub async fn next(&self) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Next).await
+    }
+
+    /// Step back to the wallpaper shown before the current one and restart the countdown.
+    pub async fn previous(&self) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Previous).await
+    }
+
+    /// Freeze the countdown. Manual switches still apply while paused.
+    pub async fn pause(&self) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Pause).await
+    }
+
+    pub async fn set_mode(
+        &self,
+        mode: RotationMode,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Mode(mode)).await
+    }
+
+    pub async fn set_output_mode(
+        &self,
+        mode: OutputMode,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::OutputMode(mode)).await
+    }
+
+    /// Play the order, interval and rotation mode of `playlist` instead of the current ones.
+    /// Play `playlist` in place of the queue. Resolves once the scheduler switched over, `None`
+    /// if it stopped.
+    pub async fn activate_playlist(&self, playlist: Playlist) -> Option<()> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(Command::Playlist(Box::new(playlist), tx))
+            .await
+            .ok()?;
+        rx.await.ok()
+    }
+
+    /// What the scheduler is showing and when it switches next. `None` if it stopped.
+    pub async fn status(&self) -> Option<Snapshot> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx.send(Command::Status(tx)).await.ok()?;
+        rx.await.ok()
+    }
+
+    /// Follow `rule`, or the interval alone without one.
+    pub async fn set_rule(
+        &self,
+        rule: Option<ActiveRule>,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Rule(rule)).await
+    }
+
+    /// Pass over wallpapers meant for another phase than `phase` from now on.
+    pub async fn set_phase(
+        &self,
+        phase: Option<SolarPhase>,
+    ) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Phase(phase)).await
+    }
+
+    /// Continue the countdown from where it was paused.
+    pub async fn resume(&self) -> Result<(), mpsc::error::SendError<Command>> {
+        self.command_tx.send(Command::Resume).await
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::path::{Path, PathBuf};
+
+    use chrono::DateTime;
+    use swww_itch_shared::{
+        backend::{BackendFuture, RecordingBackend},
+        config::WallpaperSource,
+        message::{OutputInfo, Position},
+        schedule::ScheduleRule,
+        sun::Location,
+    };
+    use tokio::time::sleep_until;
+
+    use super::*;
+
+    /// A 1x1 PNG. Only its header is ever read.
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f,
+        0x15, 0xc4, 0x89,
+    ];
+
+    /// A fresh directory holding an image for each of `names`.
+    pub(crate) fn wallpapers(test: &str, names: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("swww-itchd-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            std::fs::write(dir.join(name), PNG).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    fn builder(dir: &Path, backend: &Arc<RecordingBackend>) -> WallpaperQueueBuilder {
+        WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .with_backend(backend.clone())
+    }
+
+    /// File names of the wallpapers shown so far, along with the outputs they were shown on.
+    fn applied(backend: &RecordingBackend) -> Vec<(String, Vec<String>)> {
+        backend
+            .applied()
+            .into_iter()
+            .map(|applied| {
+                let name = Path::new(&applied.path).file_name().unwrap();
+                (name.to_string_lossy().to_string(), applied.outputs)
+            })
+            .collect()
+    }
+
+    fn shown(backend: &RecordingBackend) -> Vec<String> {
+        applied(backend).into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Let the scheduler work through the commands sent so far.
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    fn on(name: &str, outputs: &[&str]) -> (String, Vec<String>) {
+        (
+            name.to_string(),
+            outputs.iter().map(|output| output.to_string()).collect(),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn steps_through_the_queue_by_hand() {
+        let dir = wallpapers("steps", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec!["DP-1".to_string()], None));
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60))
+            .build()
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            wq.next_wallpaper().await.unwrap();
+            settle().await;
+        }
+        assert_eq!(shown(&backend), ["a.png", "b.png", "c.png"]);
+
+        wq.previous_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(shown(&backend)[3..], ["b.png"]);
+
+        let a = dir.join("a.png").to_string_lossy().to_string();
+        wq.switch_to_wallpaper(&a, None, SwitchReason::Manual)
+            .await
+            .unwrap();
+        settle().await;
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(shown(&backend)[4..], ["a.png", "b.png"]);
+
+        // A scheduled switch carries on from there an interval later.
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(shown(&backend)[6..], ["c.png"]);
+
+        // One wallpaper for every output.
+        assert!(
+            applied(&backend)
+                .iter()
+                .all(|(_, outputs)| outputs.is_empty())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shows_one_wallpaper_everywhere_in_same_mode() {
+        let dir = wallpapers("same", &["a.png", "b.png", "c.png"]);
+        let outputs = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let backend = Arc::new(RecordingBackend::new(outputs, None));
+        let wq = builder(&dir, &backend)
+            .with_output_mode(OutputMode::Same)
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+
+        assert_eq!(applied(&backend), [on("a.png", &[]), on("b.png", &[])]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_every_output_its_own_position_in_independent_mode() {
+        let dir = wallpapers("independent", &["a.png", "b.png", "c.png"]);
+        let outputs = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let backend = Arc::new(RecordingBackend::new(outputs, None));
+        let wq = builder(&dir, &backend)
+            .with_output_mode(OutputMode::Independent)
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(
+            applied(&backend),
+            [
+                on("a.png", &["DP-1"]),
+                on("b.png", &["HDMI-A-1"]),
+                on("b.png", &["DP-1"]),
+                on("c.png", &["HDMI-A-1"]),
+            ]
+        );
+
+        // Switching one output moves only that output on.
+        let a = dir.join("a.png").to_string_lossy().to_string();
+        wq.switch_to_wallpaper(&a, Some("HDMI-A-1".to_string()), SwitchReason::Manual)
+            .await
+            .unwrap();
+        settle().await;
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(
+            applied(&backend)[4..],
+            [
+                on("a.png", &["HDMI-A-1"]),
+                on("c.png", &["DP-1"]),
+                on("b.png", &["HDMI-A-1"]),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changes_the_output_mode_from_the_next_switch_on() {
+        let dir = wallpapers("output-mode", &["a.png", "b.png", "c.png"]);
+        let outputs = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let backend = Arc::new(RecordingBackend::new(outputs, None));
+        let wq = builder(&dir, &backend)
+            .with_output_mode(OutputMode::Same)
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        wq.set_output_mode(OutputMode::Independent).await.unwrap();
+        settle().await;
+        assert_eq!(applied(&backend), [on("a.png", &[])]);
+        assert_eq!(
+            wq.get_status().await.unwrap().output_mode,
+            OutputMode::Independent
+        );
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(
+            applied(&backend)[1..],
+            [on("b.png", &["DP-1"]), on("c.png", &["HDMI-A-1"])]
+        );
+        assert_eq!(wq.state.get().output_mode, Some(OutputMode::Independent));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shows_consecutive_wallpapers_side_by_side_in_spanned_mode() {
+        let dir = wallpapers("spanned", &["a.png", "b.png", "c.png"]);
+        let outputs = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        let backend = Arc::new(RecordingBackend::new(outputs, None));
+        let wq = builder(&dir, &backend)
+            .with_output_mode(OutputMode::Spanned)
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(
+            applied(&backend),
+            [
+                on("a.png", &["DP-1"]),
+                on("b.png", &["HDMI-A-1"]),
+                on("c.png", &["DP-1"]),
+                on("a.png", &["HDMI-A-1"]),
+            ]
+        );
+    }
+
+    /// Takes a second to tell whether it is ready, like `swww query` can.
+    #[derive(Debug)]
+    struct SlowBackend(RecordingBackend);
+
+    impl WallpaperBackend for SlowBackend {
+        fn set_wallpaper<'a>(
+            &'a self,
+            path: &'a str,
+            outputs: &'a [String],
+            transition: &'a TransitionOptions,
+        ) -> BackendFuture<'a, ()> {
+            self.0.set_wallpaper(path, outputs, transition)
+        }
+
+        fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>> {
+            self.0.outputs()
+        }
+
+        fn ready(&self) -> BackendFuture<'_, ()> {
+            Box::pin(async {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rearranges_while_the_backend_is_asked_whether_it_is_ready() {
+        let dir = wallpapers("rearrange", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(SlowBackend(RecordingBackend::new(vec![], None)));
+        let wq = WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .with_backend(backend.clone())
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+
+        let rearranged = tokio::time::timeout(
+            Duration::from_secs(10),
+            wq.rearrange_wallpaper("c.png", &Position::Before, "a.png"),
+        )
+        .await;
+        assert_eq!(rearranged.expect("Rearranging hung"), Ok((2, 0)));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(shown(&backend.0), ["a.png"]);
+    }
+
+    /// Answers that it is ready, but fails to show anything while `down` is set, like swww when
+    /// swww-daemon quits between the two.
+    #[derive(Debug, Default)]
+    struct GoneBackend {
+        shown: RecordingBackend,
+        down: std::sync::atomic::AtomicBool,
+    }
+
+    impl WallpaperBackend for GoneBackend {
+        fn set_wallpaper<'a>(
+            &'a self,
+            path: &'a str,
+            outputs: &'a [String],
+            transition: &'a TransitionOptions,
+        ) -> BackendFuture<'a, ()> {
+            if self.down.load(std::sync::atomic::Ordering::SeqCst) {
+                return Box::pin(async { Err(BackendError::Unavailable("gone".to_string())) });
+            }
+            self.shown.set_wallpaper(path, outputs, transition)
+        }
+
+        fn outputs(&self) -> BackendFuture<'_, Vec<OutputInfo>> {
+            self.shown.outputs()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_the_same_wallpaper_when_the_backend_goes_away() {
+        let dir = wallpapers("gone", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(GoneBackend::default());
+        let wq = WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .with_backend(backend.clone())
+            .with_interval(Duration::from_secs(60))
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+
+        backend
+            .down
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        settle().await;
+        let status = wq.get_status().await.unwrap();
+        assert_eq!(
+            status.upcoming,
+            Some(dir.join("b.png").to_string_lossy().into())
+        );
+        assert!(status.next_switch <= 1);
+
+        // Once it is back, b is shown rather than passed over.
+        backend
+            .down
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        settle().await;
+        assert_eq!(shown(&backend.shown), ["a.png", "b.png"]);
+        assert_eq!(wq.get_history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_rearrange_an_empty_queue() {
+        let dir = wallpapers("empty", &[]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let wq = builder(&dir, &backend).build().await.unwrap();
+
+        assert_eq!(
+            wq.rearrange_wallpaper("a.png", &Position::Before, "b.png")
+                .await,
+            Err(ItchError::NotInQueue("a.png".to_string()))
+        );
+
+        // An empty playlist, too.
+        let wq = builder(&wallpapers("emptied", &["a.png"]), &backend)
+            .build()
+            .await
+            .unwrap();
+        wq.create_playlist("none".to_string(), Some(vec![]))
+            .await
+            .unwrap();
+        wq.activate_playlist("none").await.unwrap();
+        assert!(
+            wq.rearrange_wallpaper("a.png", &Position::After, "a.png")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn switches_on_wake_when_the_switch_was_due_during_a_suspend() {
+        let dir = wallpapers("suspend", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let clock =
+            Clock::starting_at(DateTime::parse_from_rfc3339("2025-03-21T10:00:00+01:00").unwrap());
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60 * 60))
+            .with_clock(clock.clone())
+            .build()
+            .await
+            .unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        tokio::time::sleep(Duration::from_secs(10 * 60)).await;
+
+        // A request right after waking up sees the switch as due, and it happens right away
+        // rather than 50 minutes later.
+        clock.suspend(Duration::from_secs(2 * 60 * 60));
+        assert_eq!(wq.get_status().await.unwrap().next_switch, 0);
+        settle().await;
+        assert_eq!(shown(&backend), ["a.png", "b.png"]);
+
+        // Pausing after a suspend keeps what was left of the countdown.
+        tokio::time::sleep(Duration::from_secs(10 * 60)).await;
+        clock.suspend(Duration::from_secs(20 * 60));
+        wq.pause().await.unwrap();
+        let status = wq.get_status().await.unwrap();
+        assert!(status.paused);
+        assert!((29 * 60..=30 * 60).contains(&status.next_switch));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_a_pinned_wallpaper_until_switched_by_hand() {
+        let dir = wallpapers("pinned", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60))
+            .build()
+            .await
+            .unwrap();
+
+        let a = dir.join("a.png").to_string_lossy().to_string();
+        let meta = WallpaperMeta {
+            pinned: true,
+            ..Default::default()
+        };
+        wq.set_wallpaper_meta(&a, meta).await.unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        tokio::time::sleep(Duration::from_secs(5 * 60)).await;
+        assert_eq!(shown(&backend), ["a.png"]);
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(shown(&backend), ["a.png", "b.png"]);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(shown(&backend), ["a.png", "b.png", "c.png"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_the_duration_of_the_shown_wallpaper() {
+        let dir = wallpapers("duration", &["a.png", "b.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60 * 60))
+            .build()
+            .await
+            .unwrap();
+
+        let a = dir.join("a.png").to_string_lossy().to_string();
+        let meta = WallpaperMeta {
+            duration: Some(3 * 60 * 60),
+            ..Default::default()
+        };
+        wq.set_wallpaper_meta(&a, meta).await.unwrap();
+
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        let status = wq.get_status().await.unwrap();
+        assert_eq!(status.interval, 60 * 60);
+        assert_eq!(status.duration, 3 * 60 * 60);
+        assert!(status.next_switch > 2 * 60 * 60);
+
+        // b has no duration of its own and stays up for the interval.
+        tokio::time::sleep(Duration::from_secs(3 * 60 * 60)).await;
+        let status = wq.get_status().await.unwrap();
+        assert_eq!(shown(&backend), ["a.png", "b.png"]);
+        assert_eq!(status.duration, 60 * 60);
+    }
+
+    #[tokio::test]
+    async fn activating_a_playlist_returns_once_its_queue_is_played() {
+        let dir = wallpapers("activate", &["a.png", "b.png", "c.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let wq = builder(&dir, &backend).build().await.unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        wq.create_playlist("two".to_string(), Some(vec![path("c.png"), path("a.png")]))
+            .await
+            .unwrap();
+
+        wq.activate_playlist("two").await.unwrap();
+        assert_eq!(wq.get_queue().await, [path("c.png"), path("a.png")]);
+
+        wq.activate_playlist("default").await.unwrap();
+        assert_eq!(wq.get_queue().await.len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_the_playlist_and_times_of_the_schedule() {
+        let dir = wallpapers("schedule", &["a.png", "b.png", "c.png", "d.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let start = tokio::time::Instant::now();
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60 * 60))
+            .with_clock(Clock::starting_at(
+                DateTime::parse_from_rfc3339("2025-03-21T06:50:00+01:00").unwrap(),
+            ))
+            .build()
+            .await
+            .unwrap();
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        wq.create_playlist("day".to_string(), Some(vec![path("c.png"), path("d.png")]))
+            .await
+            .unwrap();
+
+        let rules: Vec<ScheduleRule> = serde_json::from_value(serde_json::json!([
+            { "from": "07:00", "to": "07:55", "playlist": "day", "at": "*/15 * * * *" },
+            { "from": "07:55", "to": "07:00", "playlist": "default" },
+        ]))
+        .unwrap();
+        wq.start_schedule(Schedule::new(rules, None).unwrap());
+
+        // 06:59, the default playlist waits for its hourly switch at 07:50.
+        sleep_until(at(9)).await;
+        assert_eq!(wq.playlists.lock().await.active, "default");
+        assert!(shown(&backend).is_empty());
+
+        // 07:14, the day rule switched playlists but not the wallpaper.
+        sleep_until(at(24)).await;
+        assert_eq!(wq.playlists.lock().await.active, "day");
+        let status = wq.get_status().await.unwrap();
+        assert_eq!(status.rule.as_deref(), Some("07:00-07:55 at */15 * * * *"));
+        assert_eq!(status.next_switch, 60);
+        assert!(shown(&backend).is_empty());
+
+        // 07:15, 07:30 and 07:45.
+        sleep_until(at(56)).await;
+        assert_eq!(shown(&backend), ["c.png", "d.png", "c.png"]);
+
+        // 07:55 goes back to the default playlist and its interval, counting from then.
+        sleep_until(at(124)).await;
+        assert_eq!(wq.playlists.lock().await.active, "default");
+        assert_eq!(shown(&backend), ["c.png", "d.png", "c.png"]);
+        sleep_until(at(126)).await;
+        assert_eq!(shown(&backend), ["c.png", "d.png", "c.png", "a.png"]);
+    }
+
+    const BERLIN: Location = Location {
+        latitude: 52.52,
+        longitude: 13.40,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn shows_the_wallpapers_of_the_phase_of_the_sun() {
+        let dir = wallpapers("phase", &["a.png", "b.png", "c.png", "d.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let start = tokio::time::Instant::now();
+        // Sunset is at 18:20 and dusk at 18:54.
+        let wq = builder(&dir, &backend)
+            .with_interval(Duration::from_secs(60 * 60))
+            .with_clock(Clock::starting_at(
+                DateTime::parse_from_rfc3339("2025-03-21T17:30:00+01:00").unwrap(),
+            ))
+            .build()
+            .await
+            .unwrap();
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+        let tag = async |name: &str, phase| {
+            let meta = WallpaperMeta {
+                phase: Some(phase),
+                ..Default::default()
+            };
+            let bg = dir.join(name).to_string_lossy().to_string();
+            wq.set_wallpaper_meta(&bg, meta).await.unwrap();
+        };
+        tag("a.png", SolarPhase::Day).await;
+        tag("b.png", SolarPhase::Night).await;
+        wq.start_schedule(Schedule::new(vec![], Some(BERLIN)).unwrap());
+        settle().await;
+
+        // b is passed over during the day.
+        wq.next_wallpaper().await.unwrap();
+        settle().await;
+        assert_eq!(shown(&backend), ["a.png"]);
+        assert_eq!(
+            wq.get_status().await.unwrap().upcoming,
+            Some(dir.join("c.png").to_string_lossy().to_string())
+        );
+
+        // At sunset a makes way right away, c is not tagged and stays into the night.
+        sleep_until(at(55)).await;
+        assert_eq!(shown(&backend), ["a.png", "c.png"]);
+        sleep_until(at(100)).await;
+        assert_eq!(shown(&backend), ["a.png", "c.png"]);
+
+        // An hour after sunset d, then b rather than a.
+        sleep_until(at(180)).await;
+        assert_eq!(shown(&backend), ["a.png", "c.png", "d.png", "b.png"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn plays_the_playlist_of_the_phase_of_the_sun() {
+        let dir = wallpapers("phase_playlists", &["a.png", "b.png", "c.png", "d.png"]);
+        let backend = Arc::new(RecordingBackend::new(vec![], None));
+        let start = tokio::time::Instant::now();
+        let wq = builder(&dir, &backend)
+            .with_clock(Clock::starting_at(
+                DateTime::parse_from_rfc3339("2025-03-21T18:00:00+01:00").unwrap(),
+            ))
+            .build()
+            .await
+            .unwrap();
+        let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        for (name, bgs, phase) in [
+            ("light", ["a.png", "b.png"], SolarPhase::Day),
+            ("dark", ["c.png", "d.png"], SolarPhase::Night),
+        ] {
+            let bgs = bgs.into_iter().map(path).collect();
+            wq.create_playlist(name.to_string(), Some(bgs))
+                .await
+                .unwrap();
+            wq.set_playlist_phase(name, Some(phase)).await.unwrap();
+        }
+        wq.start_schedule(Schedule::new(vec![], Some(BERLIN)).unwrap());
+
+        settle().await;
+        let playlists = wq.list_playlists().await;
+        let light = playlists.iter().find(|info| info.name == "light").unwrap();
+        assert!(light.active);
+        assert_eq!(light.phase, Some(SolarPhase::Day));
+
+        // No playlist is tagged with twilight, so light carries on until dusk.
+        sleep_until(at(30)).await;
+        assert_eq!(wq.playlists.lock().await.active, "light");
+        sleep_until(at(60)).await;
+        assert_eq!(wq.playlists.lock().await.active, "dark");
+        assert_eq!(wq.get_queue().await, [path("c.png"), path("d.png")]);
     }
 }
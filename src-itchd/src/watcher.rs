@@ -0,0 +1,408 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use swww_itch_shared::message::{ImageInfo, RejectedFile};
+use tokio::{
+    sync::mpsc,
+    task::{JoinHandle, spawn_blocking},
+};
+
+use crate::{
+    image::{self, Probed},
+    sources::SourceFilter,
+    wallpaper_queue::WallpaperQueue,
+};
+
+enum Change {
+    Added(String, ImageInfo),
+    Removed(String),
+    Renamed(String, String, ImageInfo),
+    Rejected(RejectedFile),
+    /// A directory left the watched tree, taking every wallpaper below it along.
+    RemovedDirectory(String),
+    /// The kernel dropped events, so the sources have to be scanned again.
+    Overflowed,
+}
+
+impl From<Probed> for Change {
+    fn from(probed: Probed) -> Self {
+        match probed {
+            Probed::Wallpaper(bg, info) => Change::Added(bg, info),
+            Probed::Rejected(rejected) => Change::Rejected(rejected),
+        }
+    }
+}
+
+/// A directory being watched, along with its canonical path which queue entries are based on.
+struct Watched {
+    path: PathBuf,
+    canonical: PathBuf,
+}
+
+type WatchedDirs = Arc<Mutex<HashMap<WatchDescriptor, Watched>>>;
+
+/// Stops watching when dropped.
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    watches: Watches,
+    watched: WatchedDirs,
+}
+
+impl WatchHandle {
+    /// Remove all watches. The reader thread is woken up by the resulting events and exits.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        for (wd, _) in self.watched.lock().unwrap().drain() {
+            let _ = self.watches.remove(wd);
+        }
+
+        // With nothing left to remove there would be no event to wake the reader, so make one.
+        if let Ok(wd) = self.watches.add("/", WatchMask::DELETE_SELF) {
+            let _ = self.watches.remove(wd);
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Keep the queue in sync with the contents of `sources`, including subdirectories within their
+/// depth limits.
+pub fn watch(sources: Vec<SourceFilter>, wq: WallpaperQueue) -> anyhow::Result<WatchHandle> {
+    let (handle, mut rx, _) = start_reader(sources)?;
+
+    tokio::spawn(async move {
+        while let Some(change) = rx.recv().await {
+            match change {
+                Change::Added(bg, info) => {
+                    println!("[watcher.rs]: Added {bg}");
+                    wq.add_wallpaper(bg, info).await;
+                }
+                Change::Removed(bg) => {
+                    println!("[watcher.rs]: Removed {bg}");
+                    wq.remove_wallpaper(&bg).await;
+                }
+                Change::Renamed(from, to, info) => {
+                    println!("[watcher.rs]: Renamed {from} -> {to}");
+                    wq.rename_wallpaper(&from, to, info).await;
+                }
+                Change::Rejected(rejected) => {
+                    println!(
+                        "[watcher.rs]: Rejected {}: {}",
+                        rejected.path, rejected.reason
+                    );
+                    wq.reject_file(rejected).await;
+                }
+                Change::RemovedDirectory(dir) => {
+                    println!("[watcher.rs]: Removed directory {dir}");
+                    wq.remove_wallpapers_in(&dir).await;
+                }
+                Change::Overflowed => {
+                    println!("[watcher.rs]: Missed changes, rescanning sources");
+                    let _ = wq
+                        .rescan_sources()
+                        .await
+                        .inspect_err(|err| eprintln!("Failed to rescan sources: {err}"));
+                }
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// Watch `sources` from a blocking thread, which reports changes until the handle is stopped.
+fn start_reader(
+    sources: Vec<SourceFilter>,
+) -> anyhow::Result<(WatchHandle, mpsc::UnboundedReceiver<Change>, JoinHandle<()>)> {
+    let inotify = Inotify::init()?;
+    let mut watches = inotify.watches();
+    let watched: WatchedDirs = Default::default();
+
+    for source in sources.iter() {
+        if !source.root().is_dir() {
+            eprintln!(
+                "Not watching {}: directory does not exist",
+                source.root().display()
+            );
+            continue;
+        }
+
+        for dir in source.directories(source.root()) {
+            add_watch(&mut watches, &watched, dir)?;
+        }
+        println!("[watcher.rs]: Watching {}", source.root().display());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let reader = {
+        let watches = watches.clone();
+        let watched = watched.clone();
+        let stop = stop.clone();
+        spawn_blocking(move || read_changes(inotify, watches, watched, sources, stop, tx))
+    };
+
+    let handle = WatchHandle {
+        stop,
+        watches,
+        watched,
+    };
+    Ok((handle, rx, reader))
+}
+
+fn add_watch(watches: &mut Watches, watched: &WatchedDirs, path: PathBuf) -> anyhow::Result<()> {
+    let wd = watches.add(
+        &path,
+        WatchMask::CLOSE_WRITE
+            | WatchMask::CREATE
+            | WatchMask::MOVED_TO
+            | WatchMask::MOVED_FROM
+            | WatchMask::DELETE,
+    )?;
+
+    let canonical = path.canonicalize().unwrap_or(path.clone());
+    watched
+        .lock()
+        .unwrap()
+        .insert(wd, Watched { path, canonical });
+    Ok(())
+}
+
+fn read_changes(
+    mut inotify: Inotify,
+    mut watches: Watches,
+    watched: WatchedDirs,
+    sources: Vec<SourceFilter>,
+    stop: Arc<AtomicBool>,
+    tx: mpsc::UnboundedSender<Change>,
+) {
+    let mut buffer = [0; 4096];
+    let matches = |path: &Path| sources.iter().any(|source| source.matches(path));
+
+    loop {
+        let events = match inotify.read_events_blocking(&mut buffer) {
+            Ok(events) => events,
+            Err(err) => {
+                eprintln!("[watcher.rs]: Stopped watching directories: {err}");
+                return;
+            }
+        };
+
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // A rename within or between watched directories is reported as MOVED_FROM followed by
+        // MOVED_TO sharing a cookie. A MOVED_FROM without its pair means the file left.
+        let mut moved_from: HashMap<u32, String> = HashMap::new();
+        let mut changes = Vec::new();
+
+        for event in events {
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                changes.push(Change::Overflowed);
+                continue;
+            }
+
+            let Some((path, canonical)) = resolve(&watched, &event.wd, event.name) else {
+                continue;
+            };
+
+            if event.mask.contains(EventMask::ISDIR) {
+                if event
+                    .mask
+                    .intersects(EventMask::MOVED_FROM | EventMask::DELETE)
+                {
+                    forget_directory(&mut watches, &watched, &path);
+                    changes.push(Change::RemovedDirectory(lossy(&canonical)));
+                } else if event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                {
+                    for source in sources.iter().filter(|source| source.descends_into(&path)) {
+                        for dir in source.directories(&path) {
+                            let _ = add_watch(&mut watches, &watched, dir).inspect_err(|err| {
+                                eprintln!("[watcher.rs]: Failed to watch directory: {err}")
+                            });
+                        }
+                        changes.extend(source.scan(&path).into_iter().map(Change::from));
+                    }
+                }
+                continue;
+            }
+
+            if event.mask.contains(EventMask::MOVED_FROM) {
+                moved_from.insert(event.cookie, lossy(&canonical));
+            } else if event.mask.contains(EventMask::MOVED_TO) {
+                let from = moved_from.remove(&event.cookie);
+                let probed = matches(&path).then(|| image::probe(&path));
+
+                match (from, probed) {
+                    (Some(from), Some(Probed::Wallpaper(to, info))) => {
+                        changes.push(Change::Renamed(from, to, info))
+                    }
+                    (Some(from), probed) => {
+                        changes.push(Change::Removed(from));
+                        changes.extend(probed.map(Change::from));
+                    }
+                    (None, probed) => changes.extend(probed.map(Change::from)),
+                }
+            } else if event.mask.contains(EventMask::CLOSE_WRITE) {
+                if matches(&path) {
+                    changes.push(Change::from(image::probe(&path)));
+                }
+            } else if event.mask.contains(EventMask::CREATE) {
+                // Files are only complete once written and closed, but a link is complete as soon
+                // as it is created and is never closed after writing.
+                if path.is_symlink() && path.is_file() && matches(&path) {
+                    changes.push(Change::from(image::probe(&path)));
+                }
+            } else if event.mask.contains(EventMask::DELETE) {
+                changes.push(Change::Removed(lossy(&canonical)));
+            }
+        }
+
+        changes.extend(moved_from.into_values().map(Change::Removed));
+
+        for change in changes {
+            if tx.send(change).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Drop the watches on `dir` and everything below it. A directory moved elsewhere keeps its
+/// watches, which would otherwise report events under its old path.
+fn forget_directory(watches: &mut Watches, watched: &WatchedDirs, dir: &Path) {
+    watched.lock().unwrap().retain(|wd, watched| {
+        if !watched.path.starts_with(dir) {
+            return true;
+        }
+        let _ = watches.remove(wd.clone());
+        false
+    });
+}
+
+/// Path of the event's file as seen from the source root, and as stored in the queue.
+fn resolve(
+    watched: &WatchedDirs,
+    wd: &WatchDescriptor,
+    name: Option<&OsStr>,
+) -> Option<(PathBuf, PathBuf)> {
+    let name = name?;
+    let watched = watched.lock().unwrap();
+    let dir = watched.get(wd)?;
+
+    Some((dir.path.join(name), dir.canonical.join(name)))
+}
+
+fn lossy(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use swww_itch_shared::config::WallpaperSource;
+    use tokio::time::timeout;
+
+    use crate::wallpaper_queue::wallpapers;
+
+    use super::*;
+
+    fn filter(dir: &Path) -> Vec<SourceFilter> {
+        vec![SourceFilter::new(&WallpaperSource::new(dir.to_string_lossy())).unwrap()]
+    }
+
+    async fn next_change(rx: &mut mpsc::UnboundedReceiver<Change>) -> Change {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("no change was reported")
+            .expect("the reader stopped")
+    }
+
+    #[tokio::test]
+    async fn reports_a_file_once_it_is_written() {
+        let dir = wallpapers("watch-write", &[]);
+        let images = wallpapers("watch-write-images", &["a.png"]);
+        let (_handle, mut rx, _) = start_reader(filter(&dir)).unwrap();
+
+        std::fs::copy(images.join("a.png"), dir.join("a.png")).unwrap();
+
+        // The empty file seen on creation is not rejected before its contents arrive.
+        let Change::Added(bg, _) = next_change(&mut rx).await else {
+            panic!("the file was not added");
+        };
+        assert_eq!(bg, dir.join("a.png").to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn reports_a_symlink_to_an_image() {
+        let dir = wallpapers("watch-symlink", &[]);
+        let images = wallpapers("watch-symlink-images", &["a.png"]);
+        let (_handle, mut rx, _) = start_reader(filter(&dir)).unwrap();
+
+        std::os::unix::fs::symlink(images.join("a.png"), dir.join("link.png")).unwrap();
+
+        let Change::Added(bg, _) = next_change(&mut rx).await else {
+            panic!("the link was not added");
+        };
+        assert_eq!(bg, images.join("a.png").to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn stops_the_reader() {
+        let dir = wallpapers("watch-stop", &["a.png"]);
+        let (handle, _rx, reader) = start_reader(filter(&dir)).unwrap();
+
+        drop(handle);
+        timeout(Duration::from_secs(5), reader)
+            .await
+            .expect("the reader did not stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_the_reader_with_nothing_to_watch() {
+        let dir = wallpapers("watch-nothing", &[]).join("missing");
+        let (handle, _rx, reader) = start_reader(filter(&dir)).unwrap();
+
+        drop(handle);
+        timeout(Duration::from_secs(5), reader)
+            .await
+            .expect("the reader did not stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn catches_up_on_changes_missed_by_rescanning() {
+        let dir = wallpapers("watch-rescan", &["a.png", "b.png"]);
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let wq = WallpaperQueue::builder()
+            .with_sources(vec![WallpaperSource::new(dir.to_string_lossy())])
+            .build()
+            .await
+            .unwrap();
+
+        std::fs::remove_file(dir.join("a.png")).unwrap();
+        std::fs::copy(dir.join("b.png"), dir.join("c.png")).unwrap();
+
+        wq.rescan_sources().await.unwrap();
+        assert_eq!(wq.get_queue().await, [path("b.png"), path("c.png")]);
+    }
+}
@@ -2,11 +2,18 @@ pub mod background {
     use std::path::PathBuf;
 
     use anyhow::anyhow;
+    use swww_itch_shared::config::Config;
 
+    /// Looks `name` up in the configured wallpaper directories, preferring the first one it
+    /// exists in.
     pub fn canonicalize(name: &str) -> anyhow::Result<PathBuf> {
-        Ok(std::env::home_dir()
-            .ok_or(anyhow!("Home directory not found"))?
-            .join("backgrounds")
-            .join(name))
+        let directories = Config::load_default()?.directories();
+
+        directories
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.exists())
+            .or_else(|| directories.first().map(|dir| dir.join(name)))
+            .ok_or(anyhow!("No wallpaper directories configured"))
     }
 }
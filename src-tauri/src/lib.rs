@@ -1,8 +1,10 @@
-use tauri::State;
-use tokio::sync::Mutex;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::{broadcast, Mutex};
 
 use swww_itch_shared::{
-    message::{Request, Response},
+    config::Config,
+    message::{PlaylistInfo, Request, Response, Status},
+    swww_ffi::TransitionOptions,
     unix_socket,
 };
 
@@ -20,25 +22,44 @@ fn greet(name: &str) -> String {
 
 #[tauri::command]
 async fn get_queue(state: State<'_, Mutex<AppState>>) -> Result<Vec<String>, String> {
-    let mut lock = state.lock().await;
+    let lock = state.lock().await;
 
     let conn = lock
         .itchd_socket
         .connection
-        .as_mut()
+        .as_ref()
         .ok_or("Not connected")?;
 
-    conn.send_request(Request::GetQueue)
-        .map_err(|err| err.to_string())?;
+    let Response::GetQueue(result) = conn
+        .call(Request::GetQueue)
+        .await
+        .map_err(|err| err.to_string())?
+    else {
+        return Err("Unexpected response from daemon".to_string());
+    };
+
+    result.map_err(|err| err.to_string())
+}
 
-    let Response::GetQueue(queue) = conn
-        .take_response(|r| matches!(r, Response::GetQueue(_)))
+#[tauri::command]
+async fn get_status(state: State<'_, Mutex<AppState>>) -> Result<Status, String> {
+    let lock = state.lock().await;
+
+    let conn = lock
+        .itchd_socket
+        .connection
+        .as_ref()
+        .ok_or("Not connected")?;
+
+    let Response::GetStatus(result) = conn
+        .call(Request::GetStatus)
         .await
+        .map_err(|err| err.to_string())?
     else {
-        unreachable!()
+        return Err("Unexpected response from daemon".to_string());
     };
 
-    Ok(queue)
+    result.map_err(|err| err.to_string())
 }
 
 #[tauri::command]
@@ -57,28 +78,37 @@ fn set_background(state: State<'_, Mutex<AppState>>, name: &str) -> anyhow::Resu
         .to_string();
 
     tauri::async_runtime::block_on(async move {
-        let mut lock = state.lock().await;
+        let lock = state.lock().await;
 
         let conn = lock
             .itchd_socket
             .connection
-            .as_mut()
+            .as_ref()
             .ok_or("Not connected")?;
 
-        conn.send_request(Request::SwitchToBackground(path))
-            .map_err(|err| err.to_string())?;
-
-        let Response::SwitchToBackground(b) = conn
-            .take_response(|r| matches!(r, Response::SwitchToBackground(_)))
+        let Response::SwitchToBackground(result) = conn
+            .call(Request::SwitchToBackground((path, None)))
             .await
+            .map_err(|err| err.to_string())?
         else {
-            unreachable!()
+            return Err("Unexpected response from daemon".to_string());
         };
 
-        Ok(b)
+        result.map(|_| true).map_err(|err| err.to_string())
     })
 }
 
+#[tauri::command]
+fn get_config() -> Result<Config, String> {
+    Config::load_default().map_err(|err| err.to_string())
+}
+
+/// Changes are picked up by swww-itchd on its next start.
+#[tauri::command]
+fn save_config(config: Config) -> Result<(), String> {
+    config.save_default().map_err(|err| err.to_string())
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct RearrangeBackgroundResult {
@@ -93,43 +123,124 @@ async fn rearrange_background(
     before_or_after: String,
     target_bg: String,
 ) -> Result<RearrangeBackgroundResult, String> {
-    let mut lock = state.lock().await;
+    let lock = state.lock().await;
+
+    let conn = lock
+        .itchd_socket
+        .connection
+        .as_ref()
+        .ok_or("Not connected")?;
+
+    let Response::RearrangeBackground(result) = conn
+        .call(Request::rearrange_background(
+            bg,
+            before_or_after,
+            target_bg,
+        )?)
+        .await
+        .map_err(|err| err.to_string())?
+    else {
+        return Err("Unexpected response from daemon".to_string());
+    };
+
+    let (move_index, to_index) = result.map_err(|err| err.to_string())?;
+
+    Ok(RearrangeBackgroundResult {
+        move_index,
+        to_index,
+    })
+}
+
+#[tauri::command]
+async fn list_playlists(state: State<'_, Mutex<AppState>>) -> Result<Vec<PlaylistInfo>, String> {
+    let lock = state.lock().await;
 
     let conn = lock
         .itchd_socket
         .connection
-        .as_mut()
+        .as_ref()
         .ok_or("Not connected")?;
 
-    conn.send_request(Request::rearrange_background(
-        bg,
-        before_or_after,
-        target_bg,
-    )?)
-    .map_err(|err| err.to_string())?;
+    let Response::ListPlaylists(result) = conn
+        .call(Request::ListPlaylists)
+        .await
+        .map_err(|err| err.to_string())?
+    else {
+        return Err("Unexpected response from daemon".to_string());
+    };
 
-    let Response::RearrangeBackground((ok, move_index, to_index)) = conn
-        .take_response(|r| matches!(r, Response::RearrangeBackground((_, _, _))))
+    result.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn activate_playlist(state: State<'_, Mutex<AppState>>, name: String) -> Result<(), String> {
+    let lock = state.lock().await;
+
+    let conn = lock
+        .itchd_socket
+        .connection
+        .as_ref()
+        .ok_or("Not connected")?;
+
+    let Response::ActivatePlaylist(result) = conn
+        .call(Request::ActivatePlaylist(name))
         .await
+        .map_err(|err| err.to_string())?
     else {
-        unreachable!()
+        return Err("Unexpected response from daemon".to_string());
     };
 
-    if !ok {
-        Err("Failed to rearrange background".to_string())
-    } else {
-        Ok(RearrangeBackgroundResult {
-            move_index,
-            to_index,
-        })
-    }
+    result.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn preview_transition(
+    state: State<'_, Mutex<AppState>>,
+    bg: String,
+    transition: TransitionOptions,
+) -> Result<(), String> {
+    let lock = state.lock().await;
+
+    let conn = lock
+        .itchd_socket
+        .connection
+        .as_ref()
+        .ok_or("Not connected")?;
+
+    let Response::PreviewTransition(result) = conn
+        .call(Request::PreviewTransition((bg, transition)))
+        .await
+        .map_err(|err| err.to_string())?
+    else {
+        return Err("Unexpected response from daemon".to_string());
+    };
+
+    result.map_err(|err| err.to_string())
+}
+
+/// Re-emit events pushed by the daemon to the webview as `itchd-event`.
+fn forward_events(app: AppHandle, mut events: broadcast::Receiver<Response>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(Response::Event(event)) => {
+                    let _ = app
+                        .emit("itchd-event", event)
+                        .inspect_err(|err| eprintln!("Failed to emit event: {err}"));
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let unix_socket_path = unix_socket::UnixSocketPath::RuntimeDir("swwwitch.sock")
-        .to_pathbuf()
-        .expect("Could not get XDG_RUNTIME_DIR from env");
+    let unix_socket_path = Config::load_default()
+        .and_then(|config| config.socket_path())
+        .expect("Could not determine the swww-itchd socket path");
 
     let app_state = AppState {
         // Wrapped in a block_on to create a runtime for tokio calls down the callstack
@@ -138,15 +249,35 @@ pub fn run() {
         }),
     };
 
+    let events = app_state.itchd_socket.connection.as_ref().map(|conn| {
+        let events = conn.subscribe();
+        let _ = conn
+            .send_request(Request::Subscribe)
+            .inspect_err(|err| eprintln!("Failed to subscribe to daemon events: {err}"));
+        events
+    });
+
     tauri::Builder::default()
         .manage(Mutex::new(app_state))
+        .setup(move |app| {
+            if let Some(events) = events {
+                forward_events(app.handle().clone(), events);
+            }
+            Ok(())
+        })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .invoke_handler(tauri::generate_handler![
             greet,
             set_background,
             rearrange_background,
-            get_queue
+            get_queue,
+            get_status,
+            list_playlists,
+            activate_playlist,
+            preview_transition,
+            get_config,
+            save_config
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,4 +1,4 @@
-import { createResource, createSignal, For } from "solid-js";
+import { createResource, createSignal, For, Show } from "solid-js";
 import * as tapi from "@tauri-apps/api";
 
 import { background, default as Background } from "./components/Background";
@@ -49,6 +49,23 @@ export default function Comp() {
     console.log("Background paths loaded.");
   });
 
+  const [playlists, { refetch: reloadPlaylists }] = createResource(
+    async () => await tapi.core.invoke("list_playlists", {}),
+    { initialValue: [] },
+  );
+
+  async function activatePlaylist(name) {
+    tapi.core
+      .invoke("activate_playlist", { name })
+      .then(async () => {
+        bgs[1](await background.list());
+        reloadPlaylists();
+      })
+      .catch((error) => {
+        console.error(error);
+      });
+  }
+
   async function rearrange(name, [beforeOrAfter, targetName]) {
     if (
       !name ||
@@ -84,7 +101,23 @@ export default function Comp() {
 
   return (
     <main class="h-full flex flex-col">
-      <h1 class="text-2xl font-bold mb-2">Rearrange Queued Backgrounds</h1>
+      <div class="flex items-center justify-between mb-2">
+        <h1 class="text-2xl font-bold">Rearrange Queued Backgrounds</h1>
+        <Show when={playlists().length > 1}>
+          <select
+            class="rounded-md px-2 py-1"
+            onChange={(ev) => activatePlaylist(ev.target.value)}
+          >
+            <For each={playlists()}>
+              {(playlist) => (
+                <option value={playlist.name} selected={playlist.active}>
+                  {playlist.name}
+                </option>
+              )}
+            </For>
+          </select>
+        </Show>
+      </div>
       <div class="flex flex-wrap justify-center content-start gap-8 overflow-y-auto flex-grow">
         <For each={bgs[0]()} fallback={<NoBackgroundsFound />}>
           {(bg) => <Draggable name={bg} s={{ rearrange }} />}